@@ -0,0 +1,91 @@
+//! Measures the throughput of `VCardReader` on a large synthetic export, with
+//! a base64 encoded photo in every card like phone exports have.
+//!
+//! Run with `cargo bench`, the size can be changed with
+//! `REMEMBER_BDAY_BENCH_MB` (default 200).
+
+use remember_bday::vcard::VCardReader;
+use std::io::{BufReader, Read};
+use std::time::Instant;
+
+/// Size of the base64 photo in each card, in bytes before folding.
+const PHOTO_SIZE: usize = 64 * 1024;
+
+/// Generates vCards on the fly, so the input doesn't need to fit into memory
+/// either.
+struct SyntheticExport {
+    remaining: usize,
+    card: Vec<u8>,
+    position: usize,
+    cards: usize,
+}
+
+impl SyntheticExport {
+    fn new(size: usize) -> Self {
+        SyntheticExport {
+            remaining: size,
+            card: Vec::new(),
+            position: 0,
+            cards: 0,
+        }
+    }
+
+    fn next_card(&mut self) {
+        self.cards += 1;
+        let mut card = format!(
+            "BEGIN:VCARD\r\nVERSION:3.0\r\nFN:Contact {n}\r\nN:{n};Contact;;;\r\n\
+             BDAY:1980-05-07\r\nTEL;TYPE=CELL:+43 123 {n}\r\nPHOTO;ENCODING=b;TYPE=JPEG:",
+            n = self.cards
+        );
+        let alphabet = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+        for i in 0..PHOTO_SIZE {
+            // fold after 75 octets like exporters do
+            if i > 0 && i % 74 == 0 {
+                card.push_str("\r\n ");
+            }
+            card.push(alphabet[(i * 7 + self.cards) % 64] as char);
+        }
+        card.push_str("\r\nEND:VCARD\r\n");
+        self.card = card.into_bytes();
+        self.position = 0;
+    }
+}
+
+impl Read for SyntheticExport {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.position == self.card.len() {
+            if self.remaining == 0 {
+                return Ok(0);
+            }
+            self.next_card();
+        }
+        let n = buf.len().min(self.card.len() - self.position);
+        buf[..n].copy_from_slice(&self.card[self.position..self.position + n]);
+        self.position += n;
+        self.remaining = self.remaining.saturating_sub(n);
+        Ok(n)
+    }
+}
+
+fn main() {
+    let megabytes: usize = std::env::var("REMEMBER_BDAY_BENCH_MB")
+        .ok()
+        .and_then(|mb| mb.parse().ok())
+        .unwrap_or(200);
+
+    let start = Instant::now();
+    let mut cards = 0;
+    for result in VCardReader::new(BufReader::new(SyntheticExport::new(megabytes << 20))) {
+        result.expect("synthetic vCards are valid");
+        cards += 1;
+    }
+    let seconds = start.elapsed().as_secs_f64();
+
+    println!(
+        "vcard_reader: {} cards, {} MB in {:.2} s ({:.1} MB/s)",
+        cards,
+        megabytes,
+        seconds,
+        megabytes as f64 / seconds
+    );
+}
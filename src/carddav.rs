@@ -0,0 +1,620 @@
+use crate::hash::fnv1a;
+use crate::vcard::{self, ParsedVCards};
+use crate::xml::{parse_xml, Element};
+use quick_xml::escape::escape;
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::time::Duration;
+use std::{error, fmt};
+
+/// Requests taking longer than this fail, the cached cards are used then.
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Responses can be large, as cards may contain photos.
+const MAX_RESPONSE_SIZE: u64 = 512 * 1024 * 1024;
+
+/// Lists the cards of the address book with their ETag.
+const PROPFIND: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<d:propfind xmlns:d="DAV:">
+  <d:prop><d:resourcetype/><d:getetag/></d:prop>
+</d:propfind>"#;
+
+/// Fetches all cards of the address book.
+const ADDRESSBOOK_QUERY: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<card:addressbook-query xmlns:d="DAV:" xmlns:card="urn:ietf:params:xml:ns:carddav">
+  <d:prop><d:getetag/><card:address-data/></d:prop>
+</card:addressbook-query>"#;
+
+/// Name of the file mapping the cached cards to their ETag.
+const INDEX_FILE: &str = "index.json";
+
+/// An address book on a CardDAV server (RFC 6352), e.g. Nextcloud or
+/// Radicale.
+///
+/// Cards are kept in a local cache, after the first sync only cards whose
+/// ETag changed are downloaded again.
+#[derive(Debug, Clone)]
+pub struct AddressBook {
+    /// URL of the address book collection.
+    pub url: String,
+    /// User for basic auth, if the server requires it.
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// Directory of the local copy of this address book.
+    pub cache_dir: PathBuf,
+}
+
+#[derive(Debug)]
+pub enum CardDavError {
+    HttpError(ureq::Error),
+    IoError(io::Error),
+    InvalidResponseError(String),
+    /// No cache directory is configured, so the address book was read
+    /// without one.
+    NoCacheError,
+}
+
+impl fmt::Display for CardDavError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        match self {
+            Self::HttpError(e) => write!(f, "request failed: {}", e),
+            Self::IoError(e) => write!(f, "cache not accessible: {}", e),
+            Self::InvalidResponseError(msg) => write!(f, "invalid response: {}", msg),
+            Self::NoCacheError => {
+                write!(f, "no cache directory, all cards are downloaded every time")
+            }
+        }
+    }
+}
+
+impl error::Error for CardDavError {}
+
+impl From<ureq::Error> for CardDavError {
+    fn from(e: ureq::Error) -> Self {
+        Self::HttpError(e)
+    }
+}
+
+impl From<io::Error> for CardDavError {
+    fn from(e: io::Error) -> Self {
+        Self::IoError(e)
+    }
+}
+
+/// Whether `input` is the URL of an address book rather than a path.
+pub fn is_url(input: &str) -> bool {
+    input.starts_with("http://") || input.starts_with("https://")
+}
+
+/// A resource of a multistatus response.
+#[derive(Debug, Default)]
+struct Resource {
+    href: String,
+    etag: Option<String>,
+    address_data: Option<String>,
+    is_collection: bool,
+}
+
+impl AddressBook {
+    /// Updates the local copy from the server and parses it.
+    pub fn sync(&self) -> Result<ParsedVCards, CardDavError> {
+        let cached = self.read_index()?;
+        let mut index = BTreeMap::new();
+        let mut fetched = Vec::new();
+        let mut changed = Vec::new();
+
+        if cached.is_empty() {
+            // nothing to compare with, so everything is fetched at once
+            fetched = self.request("REPORT", ADDRESSBOOK_QUERY)?;
+        } else {
+            changed = self
+                .request("PROPFIND", PROPFIND)?
+                .into_iter()
+                .filter(|resource| !resource.is_collection)
+                .filter_map(|resource| {
+                    let etag = resource.etag?;
+                    let href = resource.href;
+                    if cached.get(&href) == Some(&etag) {
+                        index.insert(href, etag);
+                        None
+                    } else {
+                        Some(href)
+                    }
+                })
+                .collect();
+            if !changed.is_empty() {
+                fetched = self.request("REPORT", &multiget(&changed))?;
+            }
+        }
+
+        fs::create_dir_all(&self.cache_dir)?;
+        for resource in fetched {
+            let (Some(etag), Some(data)) = (resource.etag, resource.address_data) else {
+                continue;
+            };
+            fs::write(self.card_path(&resource.href), data)?;
+            index.insert(resource.href, etag);
+        }
+        // changed cards missing from the response are kept as they were, with
+        // their old ETag they are fetched again next time
+        for href in changed {
+            if let Some(etag) = cached.get(&href) {
+                index.entry(href).or_insert_with(|| etag.clone());
+            }
+        }
+        for href in cached.keys().filter(|href| !index.contains_key(*href)) {
+            match fs::remove_file(self.card_path(href)) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e.into()),
+                _ => (),
+            }
+        }
+        self.write_index(&index)?;
+
+        self.read_cache()
+    }
+
+    /// Parses the cards of the last sync, e.g. while the server isn't
+    /// reachable.
+    pub fn read_cache(&self) -> Result<ParsedVCards, CardDavError> {
+        let mut parsed = ParsedVCards::default();
+        for href in self.read_index()?.keys() {
+            let cards = vcard::parse_vcards_lenient(fs::read(self.card_path(href))?);
+            parsed.vcards.extend(cards.vcards);
+            parsed.diagnostics.extend(cards.diagnostics);
+        }
+        Ok(parsed)
+    }
+
+    fn request(&self, method: &str, body: &str) -> Result<Vec<Resource>, CardDavError> {
+        let agent = ureq::Agent::config_builder()
+            .allow_non_standard_methods(true)
+            .timeout_global(Some(TIMEOUT))
+            .build()
+            .new_agent();
+        let mut request = ureq::http::Request::builder()
+            .method(method)
+            .uri(&self.url)
+            .header("Depth", "1")
+            .header("Content-Type", "application/xml; charset=utf-8");
+        if let Some(username) = &self.username {
+            let credentials = format!("{}:{}", username, self.password.as_deref().unwrap_or(""));
+            request = request.header(
+                "Authorization",
+                format!("Basic {}", encode_base64(credentials.as_bytes())),
+            );
+        }
+        let request = request
+            .body(body)
+            .map_err(|e| CardDavError::HttpError(e.into()))?;
+
+        let response = agent
+            .run(request)?
+            .body_mut()
+            .with_config()
+            .limit(MAX_RESPONSE_SIZE)
+            .read_to_string()?;
+        read_multistatus(&response)
+    }
+
+    /// Cards are cached in a file per resource, named after the hash of its
+    /// href.
+    fn card_path(&self, href: &str) -> PathBuf {
+        self.cache_dir
+            .join(format!("{:016x}.vcf", fnv1a(href.as_bytes())))
+    }
+
+    /// The ETags of the cached cards by their href.
+    fn read_index(&self) -> Result<BTreeMap<String, String>, CardDavError> {
+        let contents = match fs::read(self.cache_dir.join(INDEX_FILE)) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(BTreeMap::new()),
+            Err(e) => return Err(e.into()),
+        };
+        let index: serde_json::Value = serde_json::from_slice(&contents)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        Ok(index
+            .as_object()
+            .into_iter()
+            .flatten()
+            .filter_map(|(href, etag)| Some((href.clone(), String::from(etag.as_str()?))))
+            .collect())
+    }
+
+    fn write_index(&self, index: &BTreeMap<String, String>) -> Result<(), CardDavError> {
+        let json = serde_json::to_vec_pretty(index)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        fs::write(self.cache_dir.join(INDEX_FILE), json)?;
+        Ok(())
+    }
+}
+
+/// Fetches the cards with the given hrefs.
+fn multiget(hrefs: &[String]) -> String {
+    let hrefs: String = hrefs
+        .iter()
+        .map(|href| format!("\n  <d:href>{}</d:href>", escape(href.as_str())))
+        .collect();
+    format!(
+        r#"<?xml version="1.0" encoding="utf-8"?>
+<card:addressbook-multiget xmlns:d="DAV:" xmlns:card="urn:ietf:params:xml:ns:carddav">
+  <d:prop><d:getetag/><card:address-data/></d:prop>{}
+</card:addressbook-multiget>"#,
+        hrefs
+    )
+}
+
+/// Reads the resources of a WebDAV multistatus response (RFC 4918 section
+/// 13), only properties with status 200 are kept.
+fn read_multistatus(response: &str) -> Result<Vec<Resource>, CardDavError> {
+    let root = parse_xml(response).map_err(CardDavError::InvalidResponseError)?;
+    if root.name != "multistatus" {
+        return Err(CardDavError::InvalidResponseError(format!(
+            "expected <multistatus>, got <{}>",
+            root.name
+        )));
+    }
+
+    let mut resources = Vec::new();
+    for response in root.children_named("response") {
+        let mut resource = Resource {
+            href: text(response, "href").unwrap_or_default(),
+            ..Default::default()
+        };
+        let found = response.children_named("propstat").filter(|propstat| {
+            text(propstat, "status").is_none_or(|status| status.split(' ').nth(1) == Some("200"))
+        });
+        for prop in found.flat_map(|propstat| propstat.children_named("prop")) {
+            resource.etag = resource.etag.or_else(|| text(prop, "getetag"));
+            resource.address_data = resource.address_data.or_else(|| {
+                prop.children_named("address-data")
+                    .next()
+                    .map(|data| data.text.clone())
+            });
+            resource.is_collection |= prop
+                .children_named("resourcetype")
+                .any(|types| types.children_named("collection").next().is_some());
+        }
+        resources.push(resource);
+    }
+
+    let mut seen = HashSet::new();
+    resources.retain(|resource| !resource.href.is_empty() && seen.insert(resource.href.clone()));
+    Ok(resources)
+}
+
+/// The trimmed text of the first child `name`.
+fn text(element: &Element, name: &str) -> Option<String> {
+    element
+        .children_named(name)
+        .next()
+        .map(|child| String::from(child.text.trim()))
+}
+
+/// Encodes base64 (RFC 4648) with padding, for HTTP basic auth.
+fn encode_base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let buffer = chunk.iter().enumerate().fold(0u32, |buffer, (i, b)| {
+            buffer | u32::from(*b) << (16 - 8 * i)
+        });
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(buffer >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+
+    const COLLECTION: &str = "/dav/addressbooks/test/contacts/";
+
+    /// A request received by the fake server.
+    #[derive(Debug, Clone)]
+    struct Request {
+        method: String,
+        authorization: Option<String>,
+        body: String,
+    }
+
+    /// Cards (href, ETag, data) on the fake server and the requests it got.
+    #[derive(Default)]
+    struct Server {
+        cards: Vec<(String, String, String)>,
+        /// Hrefs of cards whose data is missing in REPORT responses.
+        withheld: Vec<String>,
+        requests: Vec<Request>,
+    }
+
+    fn card(name: &str, etag: &str) -> (String, String, String) {
+        (
+            format!(
+                "{}{}.vcf",
+                COLLECTION,
+                name.to_lowercase().replace(' ', "-")
+            ),
+            format!("\"{}\"", etag),
+            format!("BEGIN:VCARD\r\nVERSION:3.0\r\nFN:{}\r\nEND:VCARD\r\n", name),
+        )
+    }
+
+    /// Starts a CardDAV server answering PROPFIND and REPORT requests for
+    /// the address book at [`COLLECTION`], returning its URL.
+    fn start_server(server: Arc<Mutex<Server>>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}{}", listener.local_addr().unwrap(), COLLECTION);
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let request = read_request(&mut stream);
+                let mut server = server.lock().unwrap();
+                let body = respond(&server, &request);
+                server.requests.push(request);
+                write!(
+                    stream,
+                    "HTTP/1.1 207 Multi-Status\r\nContent-Type: application/xml\r\n\
+                     Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                )
+                .unwrap();
+            }
+        });
+        url
+    }
+
+    fn read_request(stream: &mut impl Read) -> Request {
+        let mut reader = BufReader::new(stream);
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        let method = String::from(line.split(' ').next().unwrap());
+
+        let mut authorization = None;
+        let mut length = 0;
+        loop {
+            line.clear();
+            reader.read_line(&mut line).unwrap();
+            let Some((name, value)) = line.trim_end().split_once(": ") else {
+                break;
+            };
+            match name.to_ascii_lowercase().as_str() {
+                "authorization" => authorization = Some(String::from(value)),
+                "content-length" => length = value.parse().unwrap(),
+                _ => (),
+            }
+        }
+        let mut body = vec![0; length];
+        reader.read_exact(&mut body).unwrap();
+        Request {
+            method,
+            authorization,
+            body: String::from_utf8(body).unwrap(),
+        }
+    }
+
+    fn respond(server: &Server, request: &Request) -> String {
+        let mut responses = Vec::new();
+        if request.method == "PROPFIND" {
+            responses.push(format!(
+                "<d:response><d:href>{}</d:href><d:propstat><d:prop>\
+                 <d:resourcetype><d:collection/><card:addressbook/></d:resourcetype>\
+                 </d:prop><d:status>HTTP/1.1 200 OK</d:status></d:propstat></d:response>",
+                COLLECTION
+            ));
+        }
+        for (href, etag, data) in &server.cards {
+            let data = match request.method.as_str() {
+                "REPORT"
+                    if request.body.contains("addressbook-multiget")
+                        && !request.body.contains(href.as_str()) =>
+                {
+                    continue
+                }
+                "REPORT" if server.withheld.contains(href) => String::new(),
+                "REPORT" => format!("<card:address-data>{}</card:address-data>", data),
+                _ => String::new(),
+            };
+            responses.push(format!(
+                "<d:response><d:href>{}</d:href><d:propstat><d:prop>\
+                 <d:getetag>{}</d:getetag>{}</d:prop>\
+                 <d:status>HTTP/1.1 200 OK</d:status></d:propstat></d:response>",
+                href,
+                escape(etag.as_str()),
+                data
+            ));
+        }
+        format!(
+            "<?xml version=\"1.0\"?>\n<d:multistatus xmlns:d=\"DAV:\" \
+             xmlns:card=\"urn:ietf:params:xml:ns:carddav\">{}</d:multistatus>",
+            responses.concat()
+        )
+    }
+
+    fn names(parsed: &ParsedVCards) -> Vec<&str> {
+        parsed
+            .vcards
+            .iter()
+            .map(|vcard| vcard.name.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn sync_incrementally() {
+        let server = Arc::new(Mutex::new(Server {
+            cards: vec![card("Allice Test", "1"), card("Bob Test", "1")],
+            ..Default::default()
+        }));
+        let cache = tempfile::tempdir().unwrap();
+        let address_book = AddressBook {
+            url: start_server(server.clone()),
+            username: Some(String::from("test")),
+            password: Some(String::from("secret")),
+            cache_dir: cache.path().join("contacts"),
+        };
+
+        // first sync, everything is fetched
+        let parsed = address_book.sync().unwrap();
+        assert_eq!(vec!["Allice Test", "Bob Test"], names(&parsed));
+        {
+            let server = server.lock().unwrap();
+            assert_eq!(1, server.requests.len());
+            assert_eq!("REPORT", server.requests[0].method);
+            assert!(server.requests[0].body.contains("addressbook-query"));
+            assert_eq!(
+                Some("Basic dGVzdDpzZWNyZXQ="),
+                server.requests[0].authorization.as_deref()
+            );
+        }
+
+        // one card changed, one was deleted and one added
+        {
+            let mut server = server.lock().unwrap();
+            server.cards = vec![
+                card("Allice Test", "1"),
+                card("Charlie Test", "1"),
+                card("Dave Test", "1"),
+            ];
+            server.cards[0].2 = server.cards[0].2.replace("Allice", "Alice");
+            server.cards[0].1 = String::from("\"2\"");
+            server.requests.clear();
+        }
+        let parsed = address_book.sync().unwrap();
+        assert_eq!(
+            vec!["Alice Test", "Charlie Test", "Dave Test"],
+            names(&parsed)
+        );
+        {
+            let server = server.lock().unwrap();
+            let methods: Vec<_> = server.requests.iter().map(|r| r.method.as_str()).collect();
+            assert_eq!(vec!["PROPFIND", "REPORT"], methods);
+            let multiget = &server.requests[1].body;
+            assert!(multiget.contains("addressbook-multiget"));
+            assert!(multiget.contains("allice-test.vcf"));
+            assert!(multiget.contains("charlie-test.vcf"));
+        }
+        // the deleted card is removed from the cache
+        assert_eq!(4, fs::read_dir(&address_book.cache_dir).unwrap().count());
+
+        // nothing changed, nothing is fetched
+        server.lock().unwrap().requests.clear();
+        let parsed = address_book.sync().unwrap();
+        assert_eq!(3, parsed.vcards.len());
+        let methods: Vec<_> = server
+            .lock()
+            .unwrap()
+            .requests
+            .iter()
+            .map(|r| r.method.clone())
+            .collect();
+        assert_eq!(vec!["PROPFIND"], methods);
+    }
+
+    #[test]
+    fn keep_cards_missing_from_multiget() {
+        let server = Arc::new(Mutex::new(Server {
+            cards: vec![card("Allice Test", "1"), card("Bob Test", "1")],
+            ..Default::default()
+        }));
+        let cache = tempfile::tempdir().unwrap();
+        let address_book = AddressBook {
+            url: start_server(server.clone()),
+            username: None,
+            password: None,
+            cache_dir: cache.path().to_path_buf(),
+        };
+        address_book.sync().unwrap();
+
+        // Bob changed, but the server doesn't send him
+        {
+            let mut server = server.lock().unwrap();
+            server.cards[1] = card("Bob Test", "2");
+            server.cards[1].2 = server.cards[1].2.replace("Bob", "Robert");
+            server.withheld = vec![server.cards[1].0.clone()];
+        }
+        let parsed = address_book.sync().unwrap();
+        assert_eq!(vec!["Allice Test", "Bob Test"], names(&parsed));
+
+        // and is fetched again next time
+        {
+            let mut server = server.lock().unwrap();
+            server.withheld.clear();
+            server.requests.clear();
+        }
+        let parsed = address_book.sync().unwrap();
+        assert_eq!(vec!["Allice Test", "Robert Test"], names(&parsed));
+        let server = server.lock().unwrap();
+        assert!(server.requests[1].body.contains("bob-test.vcf"));
+        assert!(!server.requests[1].body.contains("allice-test.vcf"));
+    }
+
+    #[test]
+    fn read_cache_when_offline() {
+        let server = Arc::new(Mutex::new(Server {
+            cards: vec![card("Allice Test", "1")],
+            ..Default::default()
+        }));
+        let cache = tempfile::tempdir().unwrap();
+        let mut address_book = AddressBook {
+            url: start_server(server),
+            username: None,
+            password: None,
+            cache_dir: cache.path().to_path_buf(),
+        };
+        address_book.sync().unwrap();
+
+        // nothing listens on port 9 (discard)
+        address_book.url = String::from("http://127.0.0.1:9/contacts/");
+        assert!(matches!(
+            address_book.sync(),
+            Err(CardDavError::HttpError(_))
+        ));
+        assert_eq!(
+            vec!["Allice Test"],
+            names(&address_book.read_cache().unwrap())
+        );
+    }
+
+    #[test]
+    fn base64() {
+        assert_eq!("SGVsbG8=", encode_base64(b"Hello"));
+        assert_eq!("SGVsbG8h", encode_base64(b"Hello!"));
+        assert_eq!("VMOkc3Q=", encode_base64("Täst".as_bytes()));
+        assert_eq!("", encode_base64(b""));
+    }
+
+    #[test]
+    fn read_multistatus_skips_missing_properties() {
+        let response = r#"<?xml version="1.0"?>
+<multistatus xmlns="DAV:">
+  <response>
+    <href>/contacts/a.vcf</href>
+    <propstat><prop><getetag>"1"</getetag></prop><status>HTTP/1.1 200 OK</status></propstat>
+    <propstat><prop><address-data/></prop><status>HTTP/1.1 404 Not Found</status></propstat>
+  </response>
+</multistatus>"#;
+
+        let resources = read_multistatus(response).unwrap();
+
+        assert_eq!(1, resources.len());
+        assert_eq!("/contacts/a.vcf", resources[0].href);
+        assert_eq!(Some("\"1\""), resources[0].etag.as_deref());
+        assert_eq!(None, resources[0].address_data);
+
+        assert!(matches!(
+            read_multistatus("<html>Login</html>"),
+            Err(CardDavError::InvalidResponseError(_))
+        ));
+    }
+}
@@ -0,0 +1,99 @@
+use std::io::{self, Read, Write};
+use std::process::{Command, Stdio};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How often to check whether the command finished.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+#[derive(Debug)]
+pub(crate) enum CommandError {
+    /// The command couldn't be run.
+    NotRunnable(io::Error),
+    /// The command or a process it started was still running.
+    TimedOut(Duration),
+    /// The command exited with another status than 0, with what it printed
+    /// to stderr.
+    Failed(String),
+}
+
+impl From<io::Error> for CommandError {
+    fn from(e: io::Error) -> Self {
+        Self::NotRunnable(e)
+    }
+}
+
+/// Runs `command`, which is split at whitespace and not run by a shell,
+/// with `input` on stdin, and returns what it printed to stdout. After
+/// `timeout` the command is killed, and its output isn't waited for any
+/// longer.
+pub(crate) fn run(command: &str, input: &[u8], timeout: Duration) -> Result<Vec<u8>, CommandError> {
+    let mut args = command.split_whitespace();
+    let program = args
+        .next()
+        .ok_or_else(|| CommandError::Failed(String::from("empty command")))?;
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    // written and read concurrently, the command would block once a pipe is
+    // full. If it exits early, its status tells why.
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    let input = input.to_vec();
+    thread::spawn(move || stdin.write_all(&input));
+    let stdout = read_in_background(child.stdout.take());
+    let stderr = read_in_background(child.stderr.take());
+
+    let deadline = Instant::now() + timeout;
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if Instant::now() >= deadline {
+            // the threads reading the pipes end with the command
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(CommandError::TimedOut(timeout));
+        }
+        thread::sleep(POLL_INTERVAL);
+    };
+
+    // a process started in the background may still hold the pipes open
+    let receive = |output: Receiver<io::Result<Vec<u8>>>| match output
+        .recv_timeout(deadline.saturating_duration_since(Instant::now()))
+    {
+        Ok(output) => Ok(output?),
+        Err(RecvTimeoutError::Timeout) => Err(CommandError::TimedOut(timeout)),
+        Err(RecvTimeoutError::Disconnected) => Err(CommandError::NotRunnable(io::Error::other(
+            "reading the output panicked",
+        ))),
+    };
+    let stdout = receive(stdout)?;
+    if status.success() {
+        Ok(stdout)
+    } else {
+        let stderr = receive(stderr)?;
+        Err(CommandError::Failed(format!(
+            "{}: {}",
+            status,
+            String::from_utf8_lossy(&stderr).trim()
+        )))
+    }
+}
+
+fn read_in_background(pipe: Option<impl Read + Send + 'static>) -> Receiver<io::Result<Vec<u8>>> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let mut output = Vec::new();
+        let result = match pipe {
+            Some(mut pipe) => pipe.read_to_end(&mut output).map(|_| output),
+            None => Ok(output),
+        };
+        // the receiver is gone if it timed out
+        let _ = sender.send(result);
+    });
+    receiver
+}
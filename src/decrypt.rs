@@ -0,0 +1,156 @@
+use crate::command::{self, CommandError};
+use age::armor::ArmoredReader;
+use age::{Decryptor, IdentityFile};
+use std::fs::File;
+use std::io::{self, BufReader, Read};
+use std::path::Path;
+use std::time::Duration;
+use std::{error, fmt};
+
+/// Command decrypting OpenPGP files if no other is configured.
+pub const GPG_COMMAND: &str = "gpg --decrypt --quiet --batch";
+
+#[derive(Debug)]
+pub enum DecryptError {
+    AgeError(age::DecryptError),
+    /// The identity file couldn't be read.
+    IdentityError(io::Error),
+    MissingIdentityError,
+    /// The decryption command couldn't be run.
+    CommandError(io::Error),
+    CommandFailedError(String),
+    /// The decryption command was still running, e.g. waiting for a
+    /// passphrase.
+    CommandTimeoutError(Duration),
+}
+
+impl fmt::Display for DecryptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        match self {
+            Self::AgeError(e) => write!(f, "age decryption failed: {}", e),
+            Self::IdentityError(e) => write!(f, "age identity not readable: {}", e),
+            Self::MissingIdentityError => write!(f, "no age identity file configured"),
+            Self::CommandError(e) => write!(f, "decryption command not runnable: {}", e),
+            Self::CommandFailedError(msg) => write!(f, "decryption command failed: {}", msg),
+            Self::CommandTimeoutError(timeout) => {
+                write!(f, "decryption command timed out after {:?}", timeout)
+            }
+        }
+    }
+}
+
+impl error::Error for DecryptError {}
+
+impl From<age::DecryptError> for DecryptError {
+    fn from(e: age::DecryptError) -> Self {
+        Self::AgeError(e)
+    }
+}
+
+impl From<CommandError> for DecryptError {
+    fn from(e: CommandError) -> Self {
+        match e {
+            CommandError::NotRunnable(e) => Self::CommandError(e),
+            CommandError::TimedOut(timeout) => Self::CommandTimeoutError(timeout),
+            CommandError::Failed(msg) => Self::CommandFailedError(msg),
+        }
+    }
+}
+
+/// Decrypts an age file, binary or armored, with the identities in
+/// `identity_file`, e.g. created by `age-keygen`.
+pub fn decrypt_age(ciphertext: &[u8], identity_file: &Path) -> Result<Vec<u8>, DecryptError> {
+    let identities = File::open(identity_file)
+        .and_then(|file| IdentityFile::from_buffer(BufReader::new(file)))
+        .map_err(DecryptError::IdentityError)?
+        .into_identities()?;
+
+    let decryptor = Decryptor::new_buffered(ArmoredReader::new(ciphertext))?;
+    let mut reader = decryptor.decrypt(identities.iter().map(|identity| identity.as_ref()))?;
+    let mut plaintext = Vec::new();
+    reader
+        .read_to_end(&mut plaintext)
+        .map_err(age::DecryptError::Io)?;
+    Ok(plaintext)
+}
+
+/// Decrypts with a command like `gpg --decrypt`, which gets the ciphertext
+/// on stdin and writes the plaintext to stdout. The command is split at
+/// whitespace, it's not run by a shell. After `timeout` it's killed.
+pub fn decrypt_with_command(
+    ciphertext: &[u8],
+    command: &str,
+    timeout: Duration,
+) -> Result<Vec<u8>, DecryptError> {
+    Ok(command::run(command, ciphertext, timeout)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use age::secrecy::ExposeSecret;
+    use age::x25519::Identity;
+
+    const PLAINTEXT: &[u8] = b"BEGIN:VCARD\r\nVERSION:4.0\r\nFN:Allice Test\r\nEND:VCARD\r\n";
+
+    #[test]
+    fn decrypt_age_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let identity = Identity::generate();
+        let identity_file = dir.path().join("key.txt");
+        std::fs::write(&identity_file, identity.to_string().expose_secret()).unwrap();
+
+        let binary = age::encrypt(&identity.to_public(), PLAINTEXT).unwrap();
+        let armored = age::encrypt_and_armor(&identity.to_public(), PLAINTEXT).unwrap();
+
+        assert_eq!(PLAINTEXT, decrypt_age(&binary, &identity_file).unwrap());
+        assert_eq!(
+            PLAINTEXT,
+            decrypt_age(armored.as_bytes(), &identity_file).unwrap()
+        );
+    }
+
+    #[test]
+    fn decrypt_age_with_wrong_identity() {
+        let dir = tempfile::tempdir().unwrap();
+        let identity_file = dir.path().join("key.txt");
+        std::fs::write(
+            &identity_file,
+            Identity::generate().to_string().expose_secret(),
+        )
+        .unwrap();
+
+        let ciphertext = age::encrypt(&Identity::generate().to_public(), PLAINTEXT).unwrap();
+
+        assert!(matches!(
+            decrypt_age(&ciphertext, &identity_file),
+            Err(DecryptError::AgeError(age::DecryptError::NoMatchingKeys))
+        ));
+        assert!(matches!(
+            decrypt_age(&ciphertext, &dir.path().join("missing.txt")),
+            Err(DecryptError::IdentityError(_))
+        ));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn decrypt_with_commands() {
+        let timeout = Duration::from_secs(30);
+        assert_eq!(
+            PLAINTEXT,
+            decrypt_with_command(PLAINTEXT, "cat", timeout).unwrap()
+        );
+        assert!(matches!(
+            decrypt_with_command(PLAINTEXT, "false", timeout),
+            Err(DecryptError::CommandFailedError(_))
+        ));
+        assert!(matches!(
+            decrypt_with_command(PLAINTEXT, "remember-bday-missing-command", timeout),
+            Err(DecryptError::CommandError(_))
+        ));
+        assert!(matches!(
+            decrypt_with_command(PLAINTEXT, "sleep 10", Duration::from_millis(100)),
+            Err(DecryptError::CommandTimeoutError(_))
+        ));
+    }
+}
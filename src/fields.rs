@@ -0,0 +1,59 @@
+use crate::vcard::{escape_text, Property};
+
+/// The properties of a vCard for a contact whose fields are stored
+/// separately, like by Thunderbird and LDAP directories. The birthday is a
+/// vCard value already, the other fields are plain text.
+pub(crate) fn contact_properties(
+    display_name: Option<&str>,
+    given: Option<&str>,
+    family: Option<&str>,
+    organization: Option<&str>,
+    nickname: Option<&str>,
+    birthday: Option<String>,
+) -> Vec<Property> {
+    let mut properties = Vec::new();
+    let mut push = |name: &str, value: String| {
+        properties.push(Property {
+            group: None,
+            name: String::from(name),
+            params: Vec::new(),
+            value,
+        })
+    };
+    if let Some(name) = display_name {
+        push("FN", escape_text(name));
+    }
+    if family.is_some() || given.is_some() {
+        let family = escape_text(family.unwrap_or_default());
+        let given = escape_text(given.unwrap_or_default());
+        push("N", format!("{};{};;;", family, given));
+    }
+    if let Some(organization) = organization {
+        push("ORG", escape_text(organization));
+    }
+    if let Some(nickname) = nickname {
+        push("NICKNAME", escape_text(nickname));
+    }
+    if let Some(bday) = birthday {
+        push("BDAY", bday);
+    }
+    properties
+}
+
+/// Combines Thunderbird's separate birthday fields, which it also uses in
+/// its address book database, to a vCard value.
+pub(crate) fn thunderbird_birthday(
+    year: Option<&str>,
+    month: Option<&str>,
+    day: Option<&str>,
+) -> Option<String> {
+    let pad = |field: &str| format!("{:0>2}", field);
+    match (year, month, day) {
+        (Some(year), Some(month), Some(day)) => {
+            Some(format!("{}-{}-{}", year, pad(month), pad(day)))
+        }
+        (None, Some(month), Some(day)) => Some(format!("--{}-{}", pad(month), pad(day))),
+        (Some(year), None, None) => Some(String::from(year)),
+        _ => None,
+    }
+}
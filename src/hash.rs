@@ -0,0 +1,7 @@
+/// FNV-1a, a hash that (unlike `DefaultHasher`) is the same in every build,
+/// e.g. for identifiers that have to stay the same.
+pub(crate) fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x100000001b3)
+    })
+}
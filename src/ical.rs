@@ -0,0 +1,588 @@
+use crate::hash::fnv1a;
+use crate::vcard::{
+    escape_text, fold, DateAndOrTime, ErrorKind, Event, EventKind, ParsedVCards, Property, Unfold,
+    VCard, VCardError,
+};
+use chrono::{DateTime, Datelike, NaiveDate, Utc};
+use std::collections::{HashMap, HashSet};
+
+/// Placeholder year for dates without a year, as Apple's birthday calendar
+/// does. 1604 is a leap year, so February 29 works too.
+const OMITTED_YEAR: i32 = 1604;
+
+/// Writes an iCalendar (RFC 5545) with a yearly recurring all-day event for
+/// each birthday and other event of the contacts, e.g. to subscribe to in a
+/// calendar application.
+///
+/// UIDs are derived from the `UID` of the contacts (or their name and
+/// birthday) and the kind and date of the events, so that the events stay
+/// the same when exporting again. For each entry of `alarm_days` a reminder
+/// is added that many days in advance. `now` is the creation time of the
+/// events (`DTSTAMP`).
+pub fn write_ics(vcards: &[VCard], alarm_days: &[u32], now: DateTime<Utc>) -> String {
+    let mut lines = vec![
+        String::from("BEGIN:VCALENDAR"),
+        String::from("VERSION:2.0"),
+        String::from("PRODID:-//remember-bday//remember-bday//EN"),
+        String::from("CALSCALE:GREGORIAN"),
+    ];
+
+    for vcard in vcards {
+        let uid = match &vcard.uid {
+            Some(uid) => String::from(uid.strip_prefix("urn:uuid:").unwrap_or(uid)),
+            None => {
+                let bday = vcard.bday.as_ref().map(ToString::to_string);
+                let key = format!("{}\0{}", vcard.name, bday.unwrap_or_default());
+                format!("{:016x}", fnv1a(key.as_bytes()))
+            }
+        };
+
+        if let Some(bday) = &vcard.bday {
+            let summary = format!("{}'s birthday", vcard.name);
+            let uid = format!("{}-birthday@remember-bday", uid);
+            lines.extend(event_lines(&uid, bday, &summary, alarm_days, now));
+        }
+        // identical events would get the same UID, which must be unique
+        let mut keys = HashSet::new();
+        for event in &vcard.events {
+            let (summary, kind) = match &event.kind {
+                EventKind::Anniversary => (
+                    format!("{}'s wedding anniversary", vcard.name),
+                    "anniversary",
+                ),
+                EventKind::Other => (format!("Special day for {}", vcard.name), "other"),
+                EventKind::Custom(label) => (format!("{}'s {}", vcard.name, label), label.as_str()),
+            };
+            // not the position, other events may be added or removed
+            let key = format!("{}\0{}", kind, event.date);
+            if !keys.insert(key.clone()) {
+                continue;
+            }
+            let uid = format!("{}-event-{:016x}@remember-bday", uid, fnv1a(key.as_bytes()));
+            lines.extend(event_lines(&uid, &event.date, &summary, alarm_days, now));
+        }
+    }
+    lines.push(String::from("END:VCALENDAR"));
+
+    let mut ics = String::new();
+    for line in lines {
+        ics.push_str(&fold(&line));
+        ics.push_str("\r\n");
+    }
+    ics
+}
+
+/// The `VEVENT` of a date, none for dates without month and day.
+fn event_lines(
+    uid: &str,
+    date: &DateAndOrTime,
+    summary: &str,
+    alarm_days: &[u32],
+    now: DateTime<Utc>,
+) -> Vec<String> {
+    let Some(start) = date.month_day().and_then(|(month, day)| {
+        NaiveDate::from_ymd_opt(date.year().unwrap_or(OMITTED_YEAR), month, day)
+    }) else {
+        return Vec::new();
+    };
+    // a plain yearly rule would skip February 29 in common years, this falls
+    // on February 28 there
+    let rrule = if (start.month(), start.day()) == (2, 29) {
+        "RRULE:FREQ=YEARLY;BYMONTH=2;BYMONTHDAY=-1"
+    } else {
+        "RRULE:FREQ=YEARLY"
+    };
+    let summary = escape_text(summary);
+
+    let mut lines = vec![
+        String::from("BEGIN:VEVENT"),
+        format!("UID:{}", escape_text(uid)),
+        format!("DTSTAMP:{}", now.format("%Y%m%dT%H%M%SZ")),
+        format!("DTSTART;VALUE=DATE:{}", start.format("%Y%m%d")),
+        String::from(rrule),
+        format!("SUMMARY:{}", summary),
+        String::from("TRANSP:TRANSPARENT"),
+    ];
+    for days in alarm_days {
+        lines.push(String::from("BEGIN:VALARM"));
+        lines.push(String::from("ACTION:DISPLAY"));
+        lines.push(format!("DESCRIPTION:{}", summary));
+        lines.push(match days {
+            0 => String::from("TRIGGER:PT0S"),
+            days => format!("TRIGGER:-P{}D", days),
+        });
+        lines.push(String::from("END:VALARM"));
+    }
+    lines.push(String::from("END:VEVENT"));
+    lines
+}
+
+/// Reads the birthdays of an iCalendar, e.g. an exported birthday calendar.
+///
+/// Every yearly recurring all-day event is a birthday, unless its summary
+/// says otherwise (as written by [`write_ics`]). The name is taken from the
+/// summary, e.g. `Allice Test's birthday`. Events are put into one card by
+/// the contact their UID was derived from by [`write_ics`], other events by
+/// their name. Other events are ignored.
+pub fn parse_ics(contents: impl AsRef<[u8]>) -> ParsedVCards {
+    let mut parsed = ParsedVCards::default();
+    let mut indices: HashMap<(Option<String>, String), usize> = HashMap::new();
+    // properties of the current VEVENT, outside of nested components
+    let mut event: Option<Vec<Property>> = None;
+    let mut nested = 0;
+
+    for line in Unfold::new(contents.as_ref()) {
+        let (line_number, line) = match line {
+            Ok(line) => line,
+            Err(e) => {
+                parsed
+                    .diagnostics
+                    .push(ErrorKind::IoError(e.to_string()).into());
+                break;
+            }
+        };
+        let result = String::from_utf8(line)
+            .map_err(|e| {
+                let line = String::from_utf8_lossy(e.as_bytes()).into_owned();
+                VCardError::from(ErrorKind::InvalidUtf8Error).at(line_number, &line)
+            })
+            .and_then(|line| match Property::parse(&line) {
+                Ok(property) => Ok((line, property)),
+                Err(e) => Err(e.at(line_number, &line)),
+            });
+        let (line, property) = match result {
+            Ok(result) => result,
+            Err(e) => {
+                parsed.diagnostics.push(e);
+                continue;
+            }
+        };
+
+        let Some(properties) = &mut event else {
+            if property.name == "BEGIN" && property.value.eq_ignore_ascii_case("VEVENT") {
+                event = Some(Vec::new());
+            }
+            continue;
+        };
+        match property.name.as_str() {
+            "BEGIN" => nested += 1,
+            "END" if nested > 0 => nested -= 1,
+            "END" => {
+                let properties = std::mem::take(properties);
+                event = None;
+                let contact = properties
+                    .iter()
+                    .find(|property| property.name == "UID")
+                    .and_then(|uid| exported_contact(&uid.value))
+                    .map(String::from);
+                match read_event(&properties) {
+                    Ok(Some((name, date, kind))) => {
+                        let key = (contact, name.clone());
+                        let index = *indices.entry(key).or_insert_with(|| {
+                            parsed.vcards.push(VCard {
+                                name,
+                                ..Default::default()
+                            });
+                            parsed.vcards.len() - 1
+                        });
+                        let vcard = &mut parsed.vcards[index];
+                        match kind {
+                            None if vcard.bday.is_none() => vcard.bday = Some(date),
+                            None => (),
+                            Some(kind) => vcard.events.push(Event { kind, date }),
+                        }
+                    }
+                    Ok(None) => (),
+                    Err(e) => parsed.diagnostics.push(e.at(line_number, &line)),
+                }
+            }
+            _ if nested == 0 => properties.push(property),
+            _ => (),
+        }
+    }
+    parsed
+}
+
+/// The contact part of a UID written by [`write_ics`], e.g. `1234` of
+/// `1234-birthday@remember-bday`.
+fn exported_contact(uid: &str) -> Option<&str> {
+    let uid = uid.strip_suffix("@remember-bday")?;
+    uid.strip_suffix("-birthday")
+        .or_else(|| uid.rsplit_once("-event-").map(|(contact, _)| contact))
+}
+
+/// Name, date and kind (`None` for a birthday) of a yearly all-day event.
+fn read_event(
+    properties: &[Property],
+) -> Result<Option<(String, DateAndOrTime, Option<EventKind>)>, VCardError> {
+    let find = |name: &str| properties.iter().find(|property| property.name == name);
+    let is_yearly = find("RRULE").is_some_and(|rrule| {
+        rrule
+            .value
+            .split(';')
+            .any(|part| part.eq_ignore_ascii_case("FREQ=YEARLY"))
+    });
+    let (Some(start), Some(summary), true) = (find("DTSTART"), find("SUMMARY"), is_yearly) else {
+        return Ok(None);
+    };
+    let is_all_day = start.has_param_value("VALUE", "DATE")
+        || (start.value.len() == 8 && start.params.is_empty());
+    if !is_all_day {
+        return Ok(None);
+    }
+
+    let in_event = |e: String| {
+        VCardError::from(ErrorKind::InvalidDateError(e)).in_card(summary.text_value().ok())
+    };
+    let date = match DateAndOrTime::parse(&start.value).map_err(in_event)? {
+        date if date.year() == Some(OMITTED_YEAR) => date.without_year(),
+        date => date,
+    };
+    let (name, kind) = read_summary(&summary.text_value()?);
+    Ok(Some((name, date, kind)))
+}
+
+/// Splits a summary like `Allice Test's birthday` into the name and the kind
+/// of the event, `None` for a birthday.
+fn read_summary(summary: &str) -> (String, Option<EventKind>) {
+    let summary = summary.trim();
+    for (suffix, kind) in [
+        ("'s birthday", None),
+        ("\u{2019}s birthday", None),
+        ("'s wedding anniversary", Some(EventKind::Anniversary)),
+        ("'s anniversary", Some(EventKind::Anniversary)),
+    ] {
+        let start = summary.len().saturating_sub(suffix.len());
+        if summary.is_char_boundary(start) && summary[start..].eq_ignore_ascii_case(suffix) {
+            return (String::from(&summary[..start]), kind);
+        }
+    }
+    match summary.strip_prefix("Special day for ") {
+        Some(name) => (String::from(name), Some(EventKind::Other)),
+        None => (String::from(summary), None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn now() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 1, 2, 3, 4, 5).unwrap()
+    }
+
+    fn events(ics: &str) -> Vec<&str> {
+        ics.split("BEGIN:VEVENT\r\n").skip(1).collect()
+    }
+
+    #[test]
+    fn write_birthdays_and_events() {
+        let vcards = [
+            VCard {
+                name: String::from("Allice Test"),
+                uid: Some(String::from("urn:uuid:1234")),
+                bday: NaiveDate::from_ymd_opt(1980, 5, 7).map(DateAndOrTime::Date),
+                events: vec![
+                    Event {
+                        kind: EventKind::Anniversary,
+                        date: DateAndOrTime::Partial {
+                            year: None,
+                            month: Some(8),
+                            day: Some(1),
+                        },
+                    },
+                    Event {
+                        kind: EventKind::Custom(String::from("name day")),
+                        date: DateAndOrTime::Text(String::from("in winter")),
+                    },
+                ],
+                ..Default::default()
+            },
+            VCard {
+                name: String::from("Bob Test"),
+                ..Default::default()
+            },
+        ];
+
+        let ics = write_ics(&vcards, &[], now());
+
+        assert_eq!(
+            "\
+BEGIN:VCALENDAR\r
+VERSION:2.0\r
+PRODID:-//remember-bday//remember-bday//EN\r
+CALSCALE:GREGORIAN\r
+BEGIN:VEVENT\r
+UID:1234-birthday@remember-bday\r
+DTSTAMP:20240102T030405Z\r
+DTSTART;VALUE=DATE:19800507\r
+RRULE:FREQ=YEARLY\r
+SUMMARY:Allice Test's birthday\r
+TRANSP:TRANSPARENT\r
+END:VEVENT\r
+BEGIN:VEVENT\r
+UID:1234-event-40d2b2474e124b13@remember-bday\r
+DTSTAMP:20240102T030405Z\r
+DTSTART;VALUE=DATE:16040801\r
+RRULE:FREQ=YEARLY\r
+SUMMARY:Allice Test's wedding anniversary\r
+TRANSP:TRANSPARENT\r
+END:VEVENT\r
+END:VCALENDAR\r
+",
+            ics
+        );
+    }
+
+    #[test]
+    fn write_leap_day_and_alarms() {
+        let vcards = [VCard {
+            name: String::from("Test, Bob"),
+            bday: NaiveDate::from_ymd_opt(1980, 2, 29).map(DateAndOrTime::Date),
+            ..Default::default()
+        }];
+
+        let ics = write_ics(&vcards, &[0, 7], now());
+
+        let events = events(&ics);
+        assert_eq!(1, events.len());
+        assert!(events[0].contains("DTSTART;VALUE=DATE:19800229\r\n"));
+        assert!(events[0].contains("RRULE:FREQ=YEARLY;BYMONTH=2;BYMONTHDAY=-1\r\n"));
+        assert!(events[0].contains("SUMMARY:Test\\, Bob's birthday\r\n"));
+        assert!(events[0].contains("TRIGGER:PT0S\r\n"));
+        assert!(events[0].contains("TRIGGER:-P7D\r\n"));
+        assert_eq!(2, events[0].matches("BEGIN:VALARM").count());
+    }
+
+    #[test]
+    fn uids_are_stable() {
+        let vcards = [VCard {
+            name: String::from("Bob Test"),
+            bday: NaiveDate::from_ymd_opt(1981, 6, 8).map(DateAndOrTime::Date),
+            ..Default::default()
+        }];
+
+        let first = write_ics(&vcards, &[], now());
+        let second = write_ics(&vcards, &[], Utc::now());
+
+        let uid = |ics: &str| {
+            ics.lines()
+                .find(|l| l.starts_with("UID:"))
+                .map(String::from)
+        };
+        assert_eq!(uid(&first), uid(&second));
+        assert_eq!(
+            Some(String::from("UID:52ddaf7b8e84fdc9-birthday@remember-bday")),
+            uid(&first)
+        );
+    }
+
+    #[test]
+    fn event_uids_are_stable() {
+        let event = |kind, month| Event {
+            kind,
+            date: DateAndOrTime::Date(NaiveDate::from_ymd_opt(2010, month, 1).unwrap()),
+        };
+        let vcard = |events| VCard {
+            name: String::from("Bob Test"),
+            events,
+            ..Default::default()
+        };
+        let uids = |vcard| {
+            write_ics(&[vcard], &[], now())
+                .lines()
+                .filter_map(|l| l.strip_prefix("UID:"))
+                .map(String::from)
+                .collect::<Vec<_>>()
+        };
+
+        let all = uids(vcard(vec![
+            event(EventKind::Anniversary, 8),
+            event(EventKind::Custom(String::from("name day")), 12),
+            event(EventKind::Other, 12),
+        ]));
+        let reordered = uids(vcard(vec![
+            event(EventKind::Other, 12),
+            event(EventKind::Custom(String::from("name day")), 12),
+        ]));
+
+        assert_eq!(3, all.len());
+        assert_ne!(all[1], all[2]);
+        assert_eq!(vec![all[2].clone(), all[1].clone()], reordered);
+
+        // contacts of the same name are told apart by their birthday
+        let born = |year| VCard {
+            name: String::from("Bob Test"),
+            bday: NaiveDate::from_ymd_opt(year, 6, 8).map(DateAndOrTime::Date),
+            ..Default::default()
+        };
+        assert_ne!(uids(born(1981)), uids(born(1991)));
+
+        // an event repeated on the card, or labeled like another kind
+        let repeated = uids(vcard(vec![
+            event(EventKind::Anniversary, 8),
+            event(EventKind::Anniversary, 8),
+            event(EventKind::Custom(String::from("anniversary")), 8),
+            event(EventKind::Other, 12),
+        ]));
+        assert_eq!(2, repeated.len());
+        assert_eq!(
+            repeated.len(),
+            repeated.iter().collect::<HashSet<_>>().len()
+        );
+    }
+
+    #[test]
+    fn parse_birthday_calendar() {
+        let input = "\
+BEGIN:VCALENDAR\r
+VERSION:2.0\r
+BEGIN:VEVENT\r
+UID:1\r
+DTSTART;VALUE=DATE:19800507\r
+RRULE:FREQ=YEARLY\r
+SUMMARY:Allice Test\u{2019}s Birthday\r
+BEGIN:VALARM\r
+TRIGGER:-P1D\r
+DESCRIPTION:Reminder\r
+END:VALARM\r
+END:VEVENT\r
+BEGIN:VEVENT\r
+DTSTART:16040229\r
+RRULE:FREQ=YEARLY;BYMONTH=2;BYMONTHDAY=-1\r
+SUMMARY:Bob\r
+  Test\r
+END:VEVENT\r
+BEGIN:VEVENT\r
+DTSTART;VALUE=DATE:20100801\r
+RRULE:FREQ=YEARLY\r
+SUMMARY:Allice Test's wedding anniversary\r
+END:VEVENT\r
+BEGIN:VEVENT\r
+DTSTART;VALUE=DATE:20240102\r
+SUMMARY:Dentist\r
+END:VEVENT\r
+BEGIN:VEVENT\r
+DTSTART:20240102T100000Z\r
+RRULE:FREQ=YEARLY\r
+SUMMARY:Weekly meeting\r
+END:VEVENT\r
+END:VCALENDAR\r
+";
+
+        let parsed = parse_ics(input);
+
+        assert!(parsed.diagnostics.is_empty(), "{:?}", parsed.diagnostics);
+        assert_eq!(
+            vec![
+                VCard {
+                    name: String::from("Allice Test"),
+                    bday: NaiveDate::from_ymd_opt(1980, 5, 7).map(DateAndOrTime::Date),
+                    events: vec![Event {
+                        kind: EventKind::Anniversary,
+                        date: DateAndOrTime::Date(NaiveDate::from_ymd_opt(2010, 8, 1).unwrap()),
+                    }],
+                    ..Default::default()
+                },
+                VCard {
+                    name: String::from("Bob Test"),
+                    bday: Some(DateAndOrTime::Partial {
+                        year: None,
+                        month: Some(2),
+                        day: Some(29)
+                    }),
+                    ..Default::default()
+                },
+            ],
+            parsed.vcards
+        );
+    }
+
+    #[test]
+    fn parse_ics_written() {
+        let vcards = vec![VCard {
+            name: String::from("Test, Bob"),
+            bday: Some(DateAndOrTime::Partial {
+                year: None,
+                month: Some(6),
+                day: Some(8),
+            }),
+            events: vec![Event {
+                kind: EventKind::Other,
+                date: DateAndOrTime::Date(NaiveDate::from_ymd_opt(2001, 2, 3).unwrap()),
+            }],
+            ..Default::default()
+        }];
+
+        let parsed = parse_ics(write_ics(&vcards, &[7], now()));
+
+        assert!(parsed.diagnostics.is_empty(), "{:?}", parsed.diagnostics);
+        assert_eq!(vcards, parsed.vcards);
+    }
+
+    #[test]
+    fn parse_ics_written_same_names() {
+        let vcard = |uid: Option<&str>, year, month| VCard {
+            name: String::from("Bob Test"),
+            uid: uid.map(String::from),
+            bday: NaiveDate::from_ymd_opt(year, month, 8).map(DateAndOrTime::Date),
+            events: vec![Event {
+                kind: EventKind::Anniversary,
+                date: DateAndOrTime::Date(NaiveDate::from_ymd_opt(year + 30, 1, 2).unwrap()),
+            }],
+            ..Default::default()
+        };
+        let vcards = vec![
+            vcard(None, 1981, 6),
+            vcard(None, 1991, 7),
+            vcard(Some("urn:uuid:1234-5678"), 2001, 8),
+        ];
+
+        let parsed = parse_ics(write_ics(&vcards, &[], now()));
+
+        assert!(parsed.diagnostics.is_empty(), "{:?}", parsed.diagnostics);
+        assert_eq!(
+            vec![
+                vcard(None, 1981, 6),
+                vcard(None, 1991, 7),
+                vcard(None, 2001, 8)
+            ],
+            parsed.vcards
+        );
+        assert_eq!(
+            Some("1234-5678"),
+            exported_contact("1234-5678-birthday@remember-bday")
+        );
+        assert_eq!(
+            Some("1234-5678"),
+            exported_contact("1234-5678-event-40d2b2474e124b13@remember-bday")
+        );
+        assert_eq!(None, exported_contact("1234-birthday@example.com"));
+    }
+
+    #[test]
+    fn parse_ics_invalid_date() {
+        let input = "\
+BEGIN:VEVENT
+DTSTART;VALUE=DATE:19801307
+RRULE:FREQ=YEARLY
+SUMMARY:Allice Test
+END:VEVENT
+";
+
+        let parsed = parse_ics(input);
+
+        assert!(parsed.vcards.is_empty());
+        assert_eq!(1, parsed.diagnostics.len());
+        assert!(matches!(
+            parsed.diagnostics[0].kind,
+            ErrorKind::InvalidDateError(_)
+        ));
+        assert_eq!(
+            Some(String::from("Allice Test")),
+            parsed.diagnostics[0].card
+        );
+        assert_eq!(Some(5), parsed.diagnostics[0].line);
+    }
+}
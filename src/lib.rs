@@ -1,39 +1,133 @@
+pub mod carddav;
+mod command;
+pub mod decrypt;
+mod fields;
+mod hash;
+pub mod ical;
+pub mod merge;
 pub mod notifications;
+pub mod plugin;
+pub mod sources;
+pub mod thunderbird;
 pub mod vcard;
+mod xml;
 
-use chrono::prelude::*;
+use chrono::{Datelike, NaiveDate};
 use notifications::{Notifier, NotifierError};
-use vcard::VCard;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use vcard::{Event, EventKind, VCard};
 
 pub struct Config {
-    pub file_path: String,
+    pub mode: Mode,
+    /// Contact files, directories, glob patterns, CardDAV URLs, commands
+    /// (`exec:` and the command) and `-` for stdin to read contacts from.
+    pub paths: Vec<String>,
     pub windows_app_id: String,
+    pub read_options: sources::ReadOptions,
+    /// Days before an event to remind of it in the iCalendar export.
+    pub ics_alarm_days: Vec<u32>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Mode {
+    /// Notify of today's birthdays.
+    Notify,
+    /// Write all birthdays as iCalendar to stdout, selected by `export-ics`
+    /// before the paths.
+    ExportIcs,
 }
 
 impl Config {
     pub fn build(
         mut args: impl Iterator<Item = String>,
-        mut env_vars: impl for<'a> Iterator<Item = (String, String)>,
+        env_vars: impl for<'a> Iterator<Item = (String, String)>,
     ) -> Result<Config, &'static str> {
         args.next();
 
-        let file_path = match args.next() {
-            Some(arg) if arg.ends_with(".vcf") => arg,
-            _ => return Err("Didn't get path to a vcf file"),
+        let mut args = args.peekable();
+        let mode = match args.next_if(|arg| arg == "export-ics") {
+            Some(_) => Mode::ExportIcs,
+            None => Mode::Notify,
+        };
+        let paths: Vec<String> = args.collect();
+        // the format of files is recognized by their content, not checked here
+        let is_valid = |arg: &String| {
+            arg == sources::STDIN
+                || sources::is_pattern(arg)
+                || carddav::is_url(arg)
+                || plugin::is_command(arg)
+                || Path::new(arg).exists()
         };
+        if paths.is_empty() || !paths.iter().all(is_valid) {
+            return Err(
+                "Didn't get paths to existing files or directories, glob patterns, CardDAV URLs, commands or - for stdin",
+            );
+        }
+
+        let mut windows_app_id = None;
+        let mut read_options = sources::ReadOptions::default();
+        let mut ics_alarm_days = Vec::new();
+        // candidates for the cache directory, the first one set is used
+        let mut cache_dirs: [Option<PathBuf>; 4] = Default::default();
+        for (key, value) in env_vars {
+            match key.as_str() {
+                "REMEMBER_BDAY_CARDDAV_USER" => read_options.carddav_username = Some(value),
+                "REMEMBER_BDAY_CARDDAV_PASSWORD" => read_options.carddav_password = Some(value),
+                "REMEMBER_BDAY_CACHE_DIR" => cache_dirs[0] = Some(PathBuf::from(value)),
+                "REMEMBER_BDAY_AGE_IDENTITY" => {
+                    read_options.age_identity = Some(PathBuf::from(value))
+                }
+                // e.g. "gpg --decrypt --quiet --batch --pinentry-mode loopback"
+                "REMEMBER_BDAY_DECRYPT_COMMAND" => read_options.decrypt_command = Some(value),
+                // in seconds
+                "REMEMBER_BDAY_COMMAND_TIMEOUT" => {
+                    read_options.command_timeout = Some(Duration::from_secs(
+                        value
+                            .trim()
+                            .parse()
+                            .map_err(|_| "Invalid REMEMBER_BDAY_COMMAND_TIMEOUT")?,
+                    ))
+                }
+                "XDG_CACHE_HOME" => cache_dirs[1] = Some(Path::new(&value).join("remember-bday")),
+                "HOME" => cache_dirs[2] = Some(Path::new(&value).join(".cache/remember-bday")),
+                "LOCALAPPDATA" => cache_dirs[3] = Some(Path::new(&value).join("remember-bday")),
+                "REMEMBER_BDAY_APP_ID" => windows_app_id = Some(value),
+                // e.g. "name=Full Name;birthday=Date of Birth;date_format=%d.%m.%Y;delimiter=tab"
+                "REMEMBER_BDAY_CSV_COLUMNS" => {
+                    read_options.csv_columns = Some(
+                        value
+                            .parse()
+                            .map_err(|_| "Invalid REMEMBER_BDAY_CSV_COLUMNS")?,
+                    )
+                }
+                // e.g. "0,7" to be reminded on the day and a week before
+                "REMEMBER_BDAY_ICS_ALARMS" => {
+                    ics_alarm_days = value
+                        .split(',')
+                        .map(|days| days.trim().parse())
+                        .collect::<Result<_, _>>()
+                        .map_err(|_| "Invalid REMEMBER_BDAY_ICS_ALARMS")?
+                }
+                _ => (),
+            }
+        }
 
-        let windows_app_id = env_vars
-            .find_map(|v| match v.0.as_str() {
-                "REMEMBER_BDAY_APP_ID" => Some(v.1),
-                _ => None,
-            })
-            .unwrap_or("remember-bday".to_string());
+        read_options.cache_dir = cache_dirs.into_iter().flatten().next();
 
         Ok(Config {
-            file_path,
-            windows_app_id,
+            mode,
+            paths,
+            windows_app_id: windows_app_id.unwrap_or("remember-bday".to_string()),
+            read_options,
+            ics_alarm_days,
         })
     }
+
+    /// The sources of the configured paths, more can be added to it.
+    pub fn contact_sources(&self) -> sources::Registry {
+        sources::Registry::from_inputs(&self.paths, &self.read_options)
+    }
 }
 
 pub fn send_bday_notifications(
@@ -43,47 +137,259 @@ pub fn send_bday_notifications(
     let today = chrono::Local::now().date_naive();
     for vcard in vcards {
         if let Some(bday) = vcard.bday {
-            if bday.month() == today.month() && bday.day() == today.day() {
+            if bday.is_anniversary_on(today) {
                 // TODO localization?
                 notifier.send_notification(format!("It's {}'s birthday today!", vcard.name))?;
             }
         }
+        for event in &vcard.events {
+            if event.date.is_anniversary_on(today) {
+                notifier.send_notification(event_message(&vcard.name, event, today))?;
+            }
+        }
     }
 
     Ok(())
 }
 
+/// Years before this are placeholders for an unknown year, e.g. Apple's 1604
+/// when `X-APPLE-OMIT-YEAR` got lost.
+const MIN_KNOWN_YEAR: i32 = 1800;
+
+fn event_message(name: &str, event: &Event, today: NaiveDate) -> String {
+    let year = event.date.year().filter(|year| *year >= MIN_KNOWN_YEAR);
+    // TODO localization?
+    match &event.kind {
+        EventKind::Anniversary => match year.map(|year| today.year() - year) {
+            Some(years) if years > 0 => format!(
+                "It's {}'s {} wedding anniversary today!",
+                name,
+                ordinal(years)
+            ),
+            _ => format!("It's {}'s wedding anniversary today!", name),
+        },
+        EventKind::Other => format!("It's a special day for {} today!", name),
+        EventKind::Custom(label) => format!("It's {}'s {} today!", name, label),
+    }
+}
+
+fn ordinal(n: i32) -> String {
+    let suffix = match (n % 10, n % 100) {
+        (_, 11..=13) => "th",
+        (1, _) => "st",
+        (2, _) => "nd",
+        (3, _) => "rd",
+        _ => "th",
+    };
+    format!("{}{}", n, suffix)
+}
+
 #[cfg(test)]
+#[allow(clippy::useless_vec)]
 mod tests {
     use super::*;
     use std::cell::RefCell;
+    use vcard::DateAndOrTime;
+
+    /// Creates an empty file in `dir`, returning its path.
+    fn create_file(dir: &tempfile::TempDir, name: &str) -> String {
+        let path = dir.path().join(name);
+        std::fs::write(&path, "").unwrap();
+        path.display().to_string()
+    }
 
     #[test]
     fn build_config_success_noenv() {
-        let test_args = vec!["ignored", "/a/path/to/a.vcf", "also ignored"];
+        let dir = tempfile::tempdir().unwrap();
+        let path = create_file(&dir, "a.vcf");
+        let test_args = vec!["ignored", path.as_str()];
         let test_env = vec![];
         let config = Config::build(
             test_args.iter().map(|s| String::from(*s)),
             test_env.into_iter(),
         )
         .unwrap();
-        assert_eq!("/a/path/to/a.vcf", config.file_path);
+        assert_eq!(Mode::Notify, config.mode);
+        assert_eq!(vec![path], config.paths);
         assert_eq!("remember-bday", config.windows_app_id);
     }
 
     #[test]
     fn build_config_success_with_appid() {
-        let test_args = vec!["ignored", "/a/path/to/a.vcf", "also ignored"];
+        let dir = tempfile::tempdir().unwrap();
+        let path = create_file(&dir, "a.vcf");
+        let test_args = vec!["ignored", path.as_str()];
         let test_env = vec![("REMEMBER_BDAY_APP_ID".to_string(), "Test.Id".to_string())];
         let config = Config::build(
             test_args.iter().map(|s| String::from(*s)),
             test_env.into_iter(),
         )
         .unwrap();
-        assert_eq!("/a/path/to/a.vcf", config.file_path);
+        assert_eq!(vec![path], config.paths);
         assert_eq!("Test.Id", config.windows_app_id);
     }
 
+    #[test]
+    fn build_config_export_ics() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = create_file(&dir, "a.vcf");
+        let test_args = ["ignored", "export-ics", &path];
+        let test_env = [("REMEMBER_BDAY_ICS_ALARMS".to_string(), "0, 7".to_string())];
+        let config = Config::build(
+            test_args.iter().map(|s| String::from(*s)),
+            test_env.into_iter(),
+        )
+        .unwrap();
+        assert_eq!(Mode::ExportIcs, config.mode);
+        assert_eq!(vec![path.as_str()], config.paths);
+        assert_eq!(vec![0, 7], config.ics_alarm_days);
+
+        let test_env = [("REMEMBER_BDAY_ICS_ALARMS".to_string(), "a day".to_string())];
+        let config = Config::build(
+            test_args.iter().map(|s| String::from(*s)),
+            test_env.into_iter(),
+        );
+        assert!(config.is_err());
+    }
+
+    #[test]
+    fn build_config_carddav() {
+        let test_args = ["ignored", "https://example.com/dav/contacts/"];
+        let test_env = [
+            ("HOME".to_string(), "/home/test".to_string()),
+            ("REMEMBER_BDAY_CARDDAV_USER".to_string(), "test".to_string()),
+            (
+                "REMEMBER_BDAY_CARDDAV_PASSWORD".to_string(),
+                "secret".to_string(),
+            ),
+            ("XDG_CACHE_HOME".to_string(), "/tmp/cache".to_string()),
+        ];
+        let config = Config::build(
+            test_args.iter().map(|s| String::from(*s)),
+            test_env.into_iter(),
+        )
+        .unwrap();
+        assert_eq!(vec!["https://example.com/dav/contacts/"], config.paths);
+        assert_eq!(
+            Some("test"),
+            config.read_options.carddav_username.as_deref()
+        );
+        assert_eq!(
+            Some("secret"),
+            config.read_options.carddav_password.as_deref()
+        );
+        assert_eq!(
+            Some(Path::new("/tmp/cache/remember-bday")),
+            config.read_options.cache_dir.as_deref()
+        );
+    }
+
+    #[test]
+    fn build_config_decryption() {
+        let dir = tempfile::tempdir().unwrap();
+        let paths = [
+            create_file(&dir, "a.vcf.age"),
+            create_file(&dir, "b.vcf.gpg"),
+        ];
+        let test_args = ["ignored", &paths[0], &paths[1]];
+        let test_env = [
+            (
+                "REMEMBER_BDAY_AGE_IDENTITY".to_string(),
+                "/home/test/.age/key.txt".to_string(),
+            ),
+            (
+                "REMEMBER_BDAY_DECRYPT_COMMAND".to_string(),
+                "gpg -d".to_string(),
+            ),
+        ];
+        let config = Config::build(
+            test_args.iter().map(|s| String::from(*s)),
+            test_env.into_iter(),
+        )
+        .unwrap();
+        assert_eq!(paths.to_vec(), config.paths);
+        assert_eq!(
+            Some(Path::new("/home/test/.age/key.txt")),
+            config.read_options.age_identity.as_deref()
+        );
+        assert_eq!(
+            Some("gpg -d"),
+            config.read_options.decrypt_command.as_deref()
+        );
+    }
+
+    #[test]
+    fn build_config_commands() {
+        let test_args = ["ignored", "exec:crm-export --all"];
+        let test_env = [("REMEMBER_BDAY_COMMAND_TIMEOUT".to_string(), "5".to_string())];
+        let config = Config::build(
+            test_args.iter().map(|s| String::from(*s)),
+            test_env.into_iter(),
+        )
+        .unwrap();
+        assert_eq!(vec!["exec:crm-export --all"], config.paths);
+        assert_eq!(
+            Some(Duration::from_secs(5)),
+            config.read_options.command_timeout
+        );
+    }
+
+    #[test]
+    fn build_config_csv_columns() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = create_file(&dir, "a.csv");
+        let test_args = ["ignored", &path];
+        let test_env = [(
+            "REMEMBER_BDAY_CSV_COLUMNS".to_string(),
+            "name=Full Name;birthday=DOB".to_string(),
+        )];
+        let config = Config::build(
+            test_args.iter().map(|s| String::from(*s)),
+            test_env.into_iter(),
+        )
+        .unwrap();
+        let columns = config.read_options.csv_columns.unwrap();
+        assert_eq!(Some("Full Name"), columns.name.as_deref());
+        assert_eq!(Some("DOB"), columns.birthday.as_deref());
+
+        let test_env = [("REMEMBER_BDAY_CSV_COLUMNS".to_string(), "name".to_string())];
+        let config = Config::build(
+            test_args.iter().map(|s| String::from(*s)),
+            test_env.into_iter(),
+        );
+        assert!(config.is_err());
+    }
+
+    #[test]
+    fn build_config_success_multiple_paths() {
+        let dir = tempfile::tempdir().unwrap();
+        let vcard = create_file(&dir, "a.vcf");
+        let other = create_file(&dir, "contacts.txt");
+        let dir_path = dir.path().display().to_string();
+        let test_args = [
+            "ignored",
+            &vcard,
+            &dir_path,
+            "/a/path/to/contacts/*.vcf",
+            &other,
+        ];
+        let test_env = [];
+        let config = Config::build(
+            test_args.iter().map(|s| String::from(*s)),
+            test_env.into_iter(),
+        )
+        .unwrap();
+        assert_eq!(
+            vec![
+                vcard,
+                dir_path,
+                String::from("/a/path/to/contacts/*.vcf"),
+                other,
+            ],
+            config.paths
+        );
+    }
+
     #[test]
     fn build_config_failure_noarg() {
         let test_args = vec!["ignored"];
@@ -96,8 +402,28 @@ mod tests {
     }
 
     #[test]
-    fn build_config_failure_wrong_type() {
-        let test_args = vec!["ignored", "/a/path/to/a.txt", "also ignored"];
+    fn build_config_stdin_and_archives() {
+        let dir = tempfile::tempdir().unwrap();
+        let paths = [
+            create_file(&dir, "a.vcf.gz"),
+            create_file(&dir, "takeout.zip"),
+        ];
+        let test_args = ["ignored", "-", &paths[0], &paths[1]];
+        let test_env = [];
+        let config = Config::build(
+            test_args.iter().map(|s| String::from(*s)),
+            test_env.into_iter(),
+        )
+        .unwrap();
+
+        assert_eq!(vec!["-", &paths[0], &paths[1]], config.paths);
+    }
+
+    #[test]
+    fn build_config_failure_missing_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = create_file(&dir, "b.vcf");
+        let test_args = vec!["ignored", path.as_str(), "/a/path/to/a.vcf"];
         let test_env = vec![];
         let config = Config::build(
             test_args.iter().map(|s| String::from(*s)),
@@ -145,10 +471,12 @@ mod tests {
             VCard {
                 name: "Test No Birthday".to_string(),
                 bday: None,
+                ..Default::default()
             },
             VCard {
                 name: "Test Birthday not today".to_string(),
-                bday: not_today,
+                bday: not_today.map(DateAndOrTime::Date),
+                ..Default::default()
             },
         ];
         send_bday_notifications(&mn, vcards).unwrap();
@@ -162,11 +490,13 @@ mod tests {
         let vcards = vec![
             VCard {
                 name: "Test 1".to_string(),
-                bday: Some(today),
+                bday: Some(DateAndOrTime::Date(today)),
+                ..Default::default()
             },
             VCard {
                 name: "Test 2".to_string(),
-                bday: Some(today),
+                bday: Some(DateAndOrTime::Date(today)),
+                ..Default::default()
             },
         ];
         send_bday_notifications(&mn, vcards).unwrap();
@@ -178,4 +508,111 @@ mod tests {
             mn.messages.into_inner()
         );
     }
+
+    #[test]
+    fn send_bday_notifications_yearless() {
+        let mn = MockNotifier::new();
+        let today = chrono::Local::now().date_naive();
+        let vcards = vec![
+            VCard {
+                name: "Test Yearless".to_string(),
+                bday: Some(DateAndOrTime::Date(today).without_year()),
+                ..Default::default()
+            },
+            VCard {
+                name: "Test Text".to_string(),
+                bday: Some(DateAndOrTime::Text("circa 1800".to_string())),
+                ..Default::default()
+            },
+        ];
+        send_bday_notifications(&mn, vcards).unwrap();
+        assert_eq!(
+            vec!["It's Test Yearless's birthday today!".to_string()],
+            mn.messages.into_inner()
+        );
+    }
+
+    #[test]
+    fn send_bday_notifications_events() {
+        let mn = MockNotifier::new();
+        let today = chrono::Local::now().date_naive();
+        // 12 years earlier is a leap year as well, so this works on February 29th
+        let wedding = today.with_year(today.year() - 12).unwrap();
+        let vcards = vec![VCard {
+            name: "Alice & Bob".to_string(),
+            structured_name: None,
+            uid: None,
+            bday: None,
+            events: vec![
+                Event {
+                    kind: EventKind::Anniversary,
+                    date: DateAndOrTime::Date(wedding),
+                },
+                Event {
+                    kind: EventKind::Anniversary,
+                    date: DateAndOrTime::Date(wedding).without_year(),
+                },
+                Event {
+                    kind: EventKind::Custom("name day".to_string()),
+                    date: DateAndOrTime::Date(today),
+                },
+                Event {
+                    kind: EventKind::Other,
+                    date: DateAndOrTime::Date(today),
+                },
+                Event {
+                    kind: EventKind::Anniversary,
+                    date: DateAndOrTime::Partial {
+                        year: None,
+                        month: None,
+                        day: Some(today.day()),
+                    },
+                },
+            ],
+        }];
+        send_bday_notifications(&mn, vcards).unwrap();
+        assert_eq!(
+            vec![
+                "It's Alice & Bob's 12th wedding anniversary today!".to_string(),
+                "It's Alice & Bob's wedding anniversary today!".to_string(),
+                "It's Alice & Bob's name day today!".to_string(),
+                "It's a special day for Alice & Bob today!".to_string(),
+            ],
+            mn.messages.into_inner()
+        );
+    }
+
+    #[test]
+    fn event_message_placeholder_years() {
+        let today = NaiveDate::from_ymd_opt(2024, 8, 1).unwrap();
+        let anniversary = |year| Event {
+            kind: EventKind::Anniversary,
+            date: DateAndOrTime::Date(NaiveDate::from_ymd_opt(year, 8, 1).unwrap()),
+        };
+        assert_eq!(
+            "It's Bob's 14th wedding anniversary today!",
+            event_message("Bob", &anniversary(2010), today)
+        );
+        for year in [1604, 1, 1799] {
+            assert_eq!(
+                "It's Bob's wedding anniversary today!",
+                event_message("Bob", &anniversary(year), today)
+            );
+        }
+    }
+
+    #[test]
+    fn ordinals() {
+        let ordinals: Vec<String> = [1, 2, 3, 4, 10, 11, 12, 13, 21, 22, 101, 111]
+            .into_iter()
+            .map(ordinal)
+            .collect();
+        assert_eq!(
+            vec![
+                "1st", "2nd", "3rd", "4th", "10th", "11th", "12th", "13th", "21st", "22nd",
+                "101st", "111th"
+            ],
+            ordinals
+        );
+    }
 }
@@ -1,6 +1,7 @@
 use remember_bday::notifications::Notifier;
-use remember_bday::{vcard, Config};
-use std::{env, fs, process};
+use remember_bday::sources::{ContactSource, Contacts};
+use remember_bday::{ical, merge, Config, Mode};
+use std::{env, process};
 
 #[cfg(target_os = "linux")]
 fn create_notifier(_: &Config) -> impl Notifier {
@@ -19,21 +20,27 @@ fn main() {
         process::exit(1);
     });
 
-    let vcards = vcard::parse_vcards(fs::read_to_string(&config.file_path).unwrap_or_else(|err| {
-        eprintln!("Problem reading file: {}", err);
-        process::exit(1);
-    }));
-
-    let vcards = match vcards {
-        Ok(vcards) => vcards,
-        Err(message) => {
-            eprintln!("{}", message);
-            return;
-        }
-    };
+    let mut contacts = Contacts::default();
+    config.contact_sources().read_contacts(&mut contacts);
+
+    for diagnostic in &contacts.diagnostics {
+        eprintln!("{}", diagnostic);
+    }
+
+    let merged = merge::merge(contacts.vcards);
+    for conflict in &merged.conflicts {
+        eprintln!("{}", conflict);
+    }
+
+    if config.mode == Mode::ExportIcs {
+        let ics = ical::write_ics(&merged.vcards, &config.ics_alarm_days, chrono::Utc::now());
+        print!("{}", ics);
+        return;
+    }
+
     let notifier = create_notifier(&config);
 
-    remember_bday::send_bday_notifications(&notifier, vcards).unwrap_or_else(|err| {
+    remember_bday::send_bday_notifications(&notifier, merged.vcards).unwrap_or_else(|err| {
         eprintln!("Problem sending notifications: {}", err);
         process::exit(1);
     })
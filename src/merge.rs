@@ -0,0 +1,363 @@
+use crate::vcard::{DateAndOrTime, VCard};
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::Hash;
+
+/// The contacts left after merging duplicates.
+#[derive(Debug, Default)]
+pub struct Merged {
+    pub vcards: Vec<VCard>,
+    pub conflicts: Vec<Conflict>,
+}
+
+/// Cards of the same contact with different birthdays. Cards with the same
+/// `UID` are merged, keeping the first birthday. Cards without a `UID` but
+/// with the same name are kept, they may be different people.
+#[derive(Debug, PartialEq)]
+pub struct Conflict {
+    pub uid: Option<String>,
+    pub name: String,
+    pub bdays: Vec<DateAndOrTime>,
+}
+
+impl fmt::Display for Conflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        let bdays: Vec<String> = self.bdays.iter().map(ToString::to_string).collect();
+        match &self.uid {
+            Some(uid) => write!(
+                f,
+                "Different birthdays for {} (UID {}): {}",
+                self.name,
+                uid,
+                bdays.join(", ")
+            ),
+            None => write!(
+                f,
+                "Different birthdays for cards named {}: {}",
+                self.name,
+                bdays.join(", ")
+            ),
+        }
+    }
+}
+
+/// Merges cards of the same contact, e.g. read from several address books,
+/// so that each birthday is only reminded of once.
+///
+/// Cards are the same contact if they have the same `UID`, or else the same
+/// name (ignoring case and whitespace) and birthday, where a birthday
+/// without a year matches the same day in any year. Cards without a birthday
+/// are only merged by `UID`. The first card is kept, missing fields, a more
+/// complete birthday and additional events are taken from its duplicates.
+pub fn merge(vcards: Vec<VCard>) -> Merged {
+    let mut conflicts: Vec<Conflict> = Vec::new();
+    let vcards = dedup_by(
+        vcards,
+        |vcard| vcard.uid.clone(),
+        |kept, duplicate| {
+            if let (Some(bday), Some(other)) = (&kept.bday, &duplicate.bday) {
+                if !is_same_date(bday, other) {
+                    add_conflict(&mut conflicts, kept.uid.as_deref(), kept, other);
+                }
+            }
+            merge_into(kept, duplicate);
+        },
+    );
+    let vcards = dedup_by_name_and_bday(vcards, &mut conflicts);
+
+    Merged { vcards, conflicts }
+}
+
+/// Merges cards with the same key into the first one, keeping the order.
+/// Cards without a key are kept as they are.
+fn dedup_by<K: Hash + Eq>(
+    vcards: Vec<VCard>,
+    key: impl Fn(&VCard) -> Option<K>,
+    mut merge: impl FnMut(&mut VCard, VCard),
+) -> Vec<VCard> {
+    let mut deduped: Vec<VCard> = Vec::with_capacity(vcards.len());
+    let mut indices: HashMap<K, usize> = HashMap::new();
+    for vcard in vcards {
+        let Some(key) = key(&vcard) else {
+            deduped.push(vcard);
+            continue;
+        };
+        match indices.get(&key) {
+            Some(&index) => merge(&mut deduped[index], vcard),
+            None => {
+                indices.insert(key, deduped.len());
+                deduped.push(vcard);
+            }
+        }
+    }
+    deduped
+}
+
+/// Merges cards with the same name and birthday into the first one, keeping
+/// the order. Cards with the same name but another birthday are kept and
+/// reported, unless both have a `UID`. Cards with different `UID`s are
+/// never merged.
+fn dedup_by_name_and_bday(vcards: Vec<VCard>, conflicts: &mut Vec<Conflict>) -> Vec<VCard> {
+    let mut deduped: Vec<VCard> = Vec::with_capacity(vcards.len());
+    let mut indices: HashMap<String, Vec<usize>> = HashMap::new();
+    for vcard in vcards {
+        let Some(bday) = vcard
+            .bday
+            .as_ref()
+            .filter(|bday| bday.month_day().is_some())
+        else {
+            deduped.push(vcard);
+            continue;
+        };
+        let same_name = indices.entry(normalize_name(&vcard.name)).or_default();
+        let same_bday = same_name.iter().find(|&&index| {
+            !have_different_uids(&deduped[index], &vcard)
+                && deduped[index]
+                    .bday
+                    .as_ref()
+                    .is_some_and(|other| is_same_date(other, bday))
+        });
+        match same_bday {
+            Some(&index) => merge_into(&mut deduped[index], vcard),
+            None => {
+                // cards with different UIDs are different people
+                let first = same_name.first().map(|&index| &deduped[index]);
+                if let Some(first) =
+                    first.filter(|first| first.uid.is_none() || vcard.uid.is_none())
+                {
+                    add_conflict(conflicts, None, first, bday);
+                }
+                same_name.push(deduped.len());
+                deduped.push(vcard);
+            }
+        }
+    }
+    deduped
+}
+
+/// Whether both cards have a `UID` and they differ, i.e. the cards are of
+/// different people.
+fn have_different_uids(vcard: &VCard, other: &VCard) -> bool {
+    matches!((&vcard.uid, &other.uid), (Some(uid), Some(other_uid)) if uid != other_uid)
+}
+
+/// Whether the dates may be the same, i.e. they are equal or have the same
+/// month and day, and not two different years.
+fn is_same_date(date: &DateAndOrTime, other: &DateAndOrTime) -> bool {
+    match (date.month_day(), other.month_day()) {
+        (Some(month_day), Some(other_month_day)) => {
+            month_day == other_month_day
+                && (date.year().is_none() || other.year().is_none() || date.year() == other.year())
+        }
+        _ => date == other,
+    }
+}
+
+/// Reports the birthday `other` of a duplicate of `kept`, found by `uid` or
+/// else by name.
+fn add_conflict(
+    conflicts: &mut Vec<Conflict>,
+    uid: Option<&str>,
+    kept: &VCard,
+    other: &DateAndOrTime,
+) {
+    let name = normalize_name(&kept.name);
+    let conflict = conflicts.iter_mut().find(|conflict| {
+        conflict.uid.as_deref() == uid && (uid.is_some() || normalize_name(&conflict.name) == name)
+    });
+    match conflict {
+        Some(conflict) if !conflict.bdays.contains(other) => conflict.bdays.push(other.clone()),
+        Some(_) => (),
+        None => conflicts.push(Conflict {
+            uid: uid.map(String::from),
+            name: kept.name.clone(),
+            bdays: kept.bday.iter().cloned().chain([other.clone()]).collect(),
+        }),
+    }
+}
+
+fn merge_into(kept: &mut VCard, duplicate: VCard) {
+    if kept.structured_name.is_none() {
+        kept.structured_name = duplicate.structured_name;
+    }
+    if kept.uid.is_none() {
+        kept.uid = duplicate.uid;
+    }
+    match (&kept.bday, &duplicate.bday) {
+        (None, _) => kept.bday = duplicate.bday,
+        // e.g. the year is only known by one of them
+        (Some(bday), Some(other))
+            if bday.year().is_none() && other.year().is_some() && is_same_date(bday, other) =>
+        {
+            kept.bday = duplicate.bday
+        }
+        _ => (),
+    }
+    for event in duplicate.events {
+        if !kept.events.contains(&event) {
+            kept.events.push(event);
+        }
+    }
+}
+
+fn normalize_name(name: &str) -> String {
+    name.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vcard::{Event, EventKind};
+    use chrono::NaiveDate;
+
+    fn bday(year: i32, month: u32, day: u32) -> Option<DateAndOrTime> {
+        NaiveDate::from_ymd_opt(year, month, day).map(DateAndOrTime::Date)
+    }
+
+    fn vcard(name: &str, uid: Option<&str>, bday: Option<DateAndOrTime>) -> VCard {
+        VCard {
+            name: String::from(name),
+            uid: uid.map(String::from),
+            bday,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn merge_by_uid() {
+        let anniversary = Event {
+            kind: EventKind::Anniversary,
+            date: bday(2010, 8, 1).unwrap(),
+        };
+        let mut with_event = vcard("Allice T.", Some("1"), None);
+        with_event.events.push(anniversary.clone());
+
+        let merged = merge(vec![
+            vcard("Allice Test", Some("1"), bday(1980, 5, 7)),
+            vcard("Bob Test", Some("2"), bday(1981, 6, 8)),
+            with_event,
+        ]);
+
+        let mut expected = vcard("Allice Test", Some("1"), bday(1980, 5, 7));
+        expected.events.push(anniversary);
+        assert_eq!(
+            vec![expected, vcard("Bob Test", Some("2"), bday(1981, 6, 8))],
+            merged.vcards
+        );
+        assert!(merged.conflicts.is_empty());
+    }
+
+    #[test]
+    fn merge_by_name_and_bday() {
+        let merged = merge(vec![
+            vcard("Allice Test", None, bday(1980, 5, 7)),
+            vcard("allice  test", Some("1"), bday(1980, 5, 7)),
+            // probably someone else
+            vcard("Allice Test", None, bday(1990, 1, 2)),
+            vcard("Carl Test", Some("3"), bday(1982, 7, 9)),
+            vcard("Carl Test", Some("4"), bday(1983, 7, 9)),
+            // without a birthday there's no telling
+            vcard("Bob Test", None, None),
+            vcard("Bob Test ", None, None),
+        ]);
+
+        assert_eq!(
+            vec![
+                vcard("Allice Test", Some("1"), bday(1980, 5, 7)),
+                vcard("Allice Test", None, bday(1990, 1, 2)),
+                vcard("Carl Test", Some("3"), bday(1982, 7, 9)),
+                vcard("Carl Test", Some("4"), bday(1983, 7, 9)),
+                vcard("Bob Test", None, None),
+                vcard("Bob Test ", None, None),
+            ],
+            merged.vcards
+        );
+        assert_eq!(
+            vec![Conflict {
+                uid: None,
+                name: String::from("Allice Test"),
+                bdays: vec![bday(1980, 5, 7).unwrap(), bday(1990, 1, 2).unwrap()],
+            }],
+            merged.conflicts
+        );
+        assert_eq!(
+            "Different birthdays for cards named Allice Test: 1980-05-07, 1990-01-02",
+            merged.conflicts[0].to_string()
+        );
+    }
+
+    #[test]
+    fn merge_dates_without_year() {
+        let without_year = || {
+            Some(DateAndOrTime::Partial {
+                year: None,
+                month: Some(5),
+                day: Some(7),
+            })
+        };
+
+        let merged = merge(vec![
+            vcard("Allice Test", None, without_year()),
+            vcard("Allice Test", None, bday(1980, 5, 7)),
+            vcard("Bob Test", Some("2"), without_year()),
+            vcard("Bob Test", Some("2"), bday(1981, 5, 7)),
+        ]);
+
+        assert_eq!(
+            vec![
+                vcard("Allice Test", None, bday(1980, 5, 7)),
+                vcard("Bob Test", Some("2"), bday(1981, 5, 7)),
+            ],
+            merged.vcards
+        );
+        assert!(merged.conflicts.is_empty());
+    }
+
+    #[test]
+    fn keep_same_name_and_bday_with_different_uids() {
+        let merged = merge(vec![
+            vcard("Allice Test", Some("1"), bday(1980, 5, 7)),
+            vcard("Allice Test", Some("2"), bday(1980, 5, 7)),
+            vcard("Allice Test", None, bday(1980, 5, 7)),
+        ]);
+
+        assert_eq!(
+            vec![
+                vcard("Allice Test", Some("1"), bday(1980, 5, 7)),
+                vcard("Allice Test", Some("2"), bday(1980, 5, 7)),
+            ],
+            merged.vcards
+        );
+        assert!(merged.conflicts.is_empty());
+    }
+
+    #[test]
+    fn report_conflicts() {
+        let merged = merge(vec![
+            vcard("Allice Test", Some("1"), bday(1980, 5, 7)),
+            vcard("Allice Test", Some("1"), bday(1980, 7, 5)),
+            vcard("Allice Test", Some("1"), None),
+            vcard("Allice Test", Some("1"), bday(1980, 7, 5)),
+        ]);
+
+        assert_eq!(
+            vec![vcard("Allice Test", Some("1"), bday(1980, 5, 7))],
+            merged.vcards
+        );
+        assert_eq!(
+            vec![Conflict {
+                uid: Some(String::from("1")),
+                name: String::from("Allice Test"),
+                bdays: vec![bday(1980, 5, 7).unwrap(), bday(1980, 7, 5).unwrap()],
+            }],
+            merged.conflicts
+        );
+        assert_eq!(
+            "Different birthdays for Allice Test (UID 1): 1980-05-07, 1980-07-05",
+            merged.conflicts[0].to_string()
+        );
+    }
+}
@@ -1,7 +1,7 @@
 #[cfg(target_os = "linux")]
 pub mod linux;
 #[cfg(target_os = "linux")]
-#[allow(dead_code)]
+#[allow(dead_code, clippy::needless_borrow)]
 mod linux_gen;
 
 #[cfg(target_os = "windows")]
@@ -0,0 +1,279 @@
+use crate::command::{self, CommandError};
+use crate::vcard::{escape_text, ParsedVCards, Property, VCard, VCardError};
+use serde_json::Value;
+use std::io;
+use std::time::Duration;
+use std::{error, fmt};
+
+/// Prefix of inputs that are commands, e.g. `exec:crm-export --all`.
+pub const PREFIX: &str = "exec:";
+
+/// Commands running longer than this are killed if no other timeout is
+/// configured.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Version of the JSON output this program understands.
+const VERSION: u64 = 1;
+
+/// An external command printing contacts to stdout, for contact stores that
+/// aren't supported otherwise.
+///
+/// The command may print anything that can be read from a file, e.g.
+/// vCards, or JSON like this, with dates like `BDAY` values of vCards:
+///
+/// ```json
+/// {
+///   "version": 1,
+///   "contacts": [
+///     {"name": "Allice Test", "birthday": "1980-05-07", "anniversary": "2005-06-08"},
+///     {"uid": "42", "name": "Bob Test", "birthday": "--06-08"}
+///   ]
+/// }
+/// ```
+///
+/// Only `name` is required, unknown fields are ignored. The command fails by
+/// exiting with another status than 0, what it printed to stderr is
+/// reported then.
+#[derive(Debug, Clone)]
+pub struct Plugin {
+    /// Program and arguments, separated by whitespace. The command isn't run
+    /// by a shell.
+    pub command: String,
+    /// After this the command is killed, and its output isn't waited for
+    /// any longer.
+    pub timeout: Duration,
+}
+
+#[derive(Debug)]
+pub enum PluginError {
+    /// The command couldn't be run.
+    CommandError(io::Error),
+    /// The command or a process it started was still running.
+    TimeoutError(Duration),
+    FailedError(String),
+    InvalidOutputError(String),
+}
+
+impl fmt::Display for PluginError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        match self {
+            Self::CommandError(e) => write!(f, "command not runnable: {}", e),
+            Self::TimeoutError(timeout) => write!(f, "command timed out after {:?}", timeout),
+            Self::FailedError(msg) => write!(f, "command failed: {}", msg),
+            Self::InvalidOutputError(msg) => write!(f, "invalid output: {}", msg),
+        }
+    }
+}
+
+impl error::Error for PluginError {}
+
+impl From<io::Error> for PluginError {
+    fn from(e: io::Error) -> Self {
+        Self::CommandError(e)
+    }
+}
+
+impl From<CommandError> for PluginError {
+    fn from(e: CommandError) -> Self {
+        match e {
+            CommandError::NotRunnable(e) => Self::CommandError(e),
+            CommandError::TimedOut(timeout) => Self::TimeoutError(timeout),
+            CommandError::Failed(msg) => Self::FailedError(msg),
+        }
+    }
+}
+
+/// Whether `input` is a command rather than a path.
+pub fn is_command(input: &str) -> bool {
+    input.starts_with(PREFIX)
+}
+
+impl Plugin {
+    /// The plugin for an input like `exec:crm-export --all`.
+    pub fn new(input: &str, timeout: Duration) -> Self {
+        Plugin {
+            command: String::from(input.strip_prefix(PREFIX).unwrap_or(input)),
+            timeout,
+        }
+    }
+
+    /// Runs the command and returns what it printed to stdout.
+    pub fn run(&self) -> Result<Vec<u8>, PluginError> {
+        Ok(command::run(&self.command, &[], self.timeout)?)
+    }
+}
+
+/// Whether the output is JSON rather than e.g. vCards.
+pub fn is_json(output: &[u8]) -> bool {
+    output.trim_ascii_start().starts_with(b"{")
+}
+
+/// Parses the JSON output of a command, skipping broken contacts.
+pub fn parse_contacts(output: &[u8]) -> Result<ParsedVCards, PluginError> {
+    let json: Value = serde_json::from_slice(output)
+        .map_err(|e| PluginError::InvalidOutputError(e.to_string()))?;
+    match json.get("version").and_then(Value::as_u64) {
+        Some(VERSION) => (),
+        Some(version) => {
+            return Err(PluginError::InvalidOutputError(format!(
+                "unsupported version {}",
+                version
+            )))
+        }
+        None => return Err(PluginError::InvalidOutputError(String::from("no version"))),
+    }
+    let contacts = json
+        .get("contacts")
+        .and_then(Value::as_array)
+        .ok_or_else(|| PluginError::InvalidOutputError(String::from("no contacts")))?;
+
+    Ok(contacts.iter().map(read_contact).collect())
+}
+
+/// Converts the contact to the properties of a vCard, so that it's read just
+/// like one.
+fn read_contact(contact: &Value) -> Result<VCard, VCardError> {
+    // a date may be given as number, e.g. 19800507
+    let field = |name| match contact.get(name) {
+        None | Some(Value::Null) => None,
+        Some(Value::String(value)) => Some(value.clone()),
+        Some(value) => Some(value.to_string()),
+    };
+    let properties: Vec<_> = [
+        ("UID", field("uid").map(|uid| escape_text(&uid))),
+        ("FN", field("name").map(|name| escape_text(&name))),
+        ("BDAY", field("birthday")),
+        ("ANNIVERSARY", field("anniversary")),
+    ]
+    .into_iter()
+    .filter_map(|(name, value)| {
+        Some(Property {
+            group: None,
+            name: String::from(name),
+            params: Vec::new(),
+            value: value?,
+        })
+    })
+    .collect();
+
+    VCard::from_properties(&properties).map_err(|e| e.in_card(field("name")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vcard::{DateAndOrTime, ErrorKind, Event, EventKind};
+    use chrono::NaiveDate;
+    use std::time::Instant;
+
+    #[test]
+    fn parse_json_contacts() {
+        let output = br#"{
+            "version": 1,
+            "contacts": [
+                {"name": "Allice Test", "birthday": "1980-05-07", "anniversary": "2005-06-08"},
+                {"uid": "42", "name": "Bob Test", "birthday": "--06-08", "phone": "123"},
+                {"uid": "x,y\\z;1", "name": "Dave Test"},
+                {"name": "Carl Test", "birthday": 19820709},
+                {"birthday": "1983-08-10"}
+            ]
+        }"#;
+
+        let parsed = parse_contacts(output).unwrap();
+
+        assert_eq!(
+            vec![
+                VCard {
+                    name: String::from("Allice Test"),
+                    bday: NaiveDate::from_ymd_opt(1980, 5, 7).map(DateAndOrTime::Date),
+                    events: vec![Event {
+                        kind: EventKind::Anniversary,
+                        date: DateAndOrTime::Date(NaiveDate::from_ymd_opt(2005, 6, 8).unwrap()),
+                    }],
+                    ..Default::default()
+                },
+                VCard {
+                    uid: Some(String::from("42")),
+                    name: String::from("Bob Test"),
+                    bday: Some(DateAndOrTime::Partial {
+                        year: None,
+                        month: Some(6),
+                        day: Some(8)
+                    }),
+                    ..Default::default()
+                },
+                VCard {
+                    uid: Some(String::from("x,y\\z;1")),
+                    name: String::from("Dave Test"),
+                    ..Default::default()
+                },
+                VCard {
+                    name: String::from("Carl Test"),
+                    bday: NaiveDate::from_ymd_opt(1982, 7, 9).map(DateAndOrTime::Date),
+                    ..Default::default()
+                },
+            ],
+            parsed.vcards
+        );
+        assert_eq!(1, parsed.diagnostics.len());
+        assert!(matches!(parsed.diagnostics[0].kind, ErrorKind::NoNameError));
+    }
+
+    #[test]
+    fn reject_unknown_versions() {
+        assert!(matches!(
+            parse_contacts(br#"{"version": 2, "contacts": []}"#),
+            Err(PluginError::InvalidOutputError(_))
+        ));
+        assert!(matches!(
+            parse_contacts(br#"{"contacts": []}"#),
+            Err(PluginError::InvalidOutputError(_))
+        ));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn run_commands() {
+        let plugin = Plugin::new("exec:echo BEGIN:VCARD", DEFAULT_TIMEOUT);
+        assert_eq!(b"BEGIN:VCARD\n".to_vec(), plugin.run().unwrap());
+
+        let plugin = Plugin::new("exec:ls /remember-bday-missing", DEFAULT_TIMEOUT);
+        assert!(matches!(plugin.run(), Err(PluginError::FailedError(_))));
+
+        let plugin = Plugin::new("exec:remember-bday-missing-command", DEFAULT_TIMEOUT);
+        assert!(matches!(plugin.run(), Err(PluginError::CommandError(_))));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn kill_commands_after_timeout() {
+        let plugin = Plugin::new("exec:sleep 10", Duration::from_millis(100));
+        let start = Instant::now();
+
+        let result = plugin.run();
+
+        assert!(matches!(result, Err(PluginError::TimeoutError(_))));
+        assert!(start.elapsed() < Duration::from_secs(5));
+        assert_eq!(
+            "command timed out after 100ms",
+            result.unwrap_err().to_string()
+        );
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn stop_waiting_for_background_processes() {
+        let dir = tempfile::tempdir().unwrap();
+        let script = dir.path().join("export.sh");
+        // exits at once, but the background process keeps stdout open
+        std::fs::write(&script, "echo BEGIN:VCARD\nsleep 10 &\n").unwrap();
+        let plugin = Plugin::new(
+            &format!("exec:sh {}", script.display()),
+            Duration::from_millis(500),
+        );
+        let start = Instant::now();
+
+        assert!(matches!(plugin.run(), Err(PluginError::TimeoutError(_))));
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+}
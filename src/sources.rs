@@ -0,0 +1,1374 @@
+use crate::carddav::{self, AddressBook, CardDavError};
+use crate::decrypt::{self, DecryptError};
+use crate::ical;
+use crate::plugin::{self, Plugin, PluginError};
+use crate::thunderbird::{self, ThunderbirdError};
+use crate::vcard::{self, ColumnMapping, ParsedVCards, VCard, VCardError, VCardReader};
+use flate2::bufread::MultiGzDecoder;
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Cursor};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use std::{error, fmt};
+use zip::ZipArchive;
+
+/// Extension of the files read from directories, e.g. a vdir as synced by
+/// vdirsyncer with one file per contact.
+const VCARD_EXTENSION: &str = "vcf";
+
+/// Extensions of jCard files. Files with other extensions are read as jCard
+/// too if they start like JSON.
+const JCARD_EXTENSIONS: [&str; 2] = ["json", "jcard"];
+
+/// Extensions of xCard files. Files with other extensions are read as xCard
+/// too if they start like XML.
+const XCARD_EXTENSIONS: [&str; 2] = ["xml", "xcard"];
+
+/// Extension of CSV exports, e.g. of Google Contacts or Outlook.
+const CSV_EXTENSION: &str = "csv";
+
+/// Extension of iCalendar files, e.g. exported birthday calendars. Files
+/// with other extensions are read as iCalendar too if they start with
+/// `BEGIN:VCALENDAR`.
+const ICS_EXTENSION: &str = "ics";
+
+/// Extension of LDIF files, e.g. exported by Thunderbird or an LDAP
+/// directory.
+const LDIF_EXTENSION: &str = "ldif";
+
+/// Extension of Thunderbird's address book databases, e.g. `abook.sqlite`.
+/// They are recognized by their content.
+const THUNDERBIRD_EXTENSION: &str = "sqlite";
+
+/// Extensions of compressed or encrypted files, e.g. `contacts.vcf.gz` or
+/// `contacts.vcf.age`. The format is judged by the rest of the name then.
+const WRAPPER_EXTENSIONS: [&str; 5] = ["gz", "age", "gpg", "pgp", "asc"];
+
+/// Extensions of binary OpenPGP files. Files with other extensions are only
+/// taken for binary OpenPGP if they aren't named like another format.
+const OPENPGP_EXTENSIONS: [&str; 2] = ["gpg", "pgp"];
+
+/// Extension of zip archives, e.g. of Google Takeout. The contact files in
+/// them are read.
+const ZIP_EXTENSION: &str = "zip";
+
+const GZIP_MAGIC: &[u8] = b"\x1f\x8b";
+const ZIP_MAGIC: &[u8] = b"PK\x03\x04";
+const SQLITE_MAGIC: &[u8] = b"SQLite format 3\0";
+const AGE_MAGIC: &[u8] = b"age-encryption.org/v1\n";
+const AGE_ARMOR_MAGIC: &[u8] = b"-----BEGIN AGE ENCRYPTED FILE-----";
+const PGP_ARMOR_MAGIC: &[u8] = b"-----BEGIN PGP MESSAGE-----";
+
+/// Input standing for stdin, e.g. to read the output of `khard export`.
+pub const STDIN: &str = "-";
+
+/// How inputs are read.
+#[derive(Debug, Clone, Default)]
+pub struct ReadOptions {
+    /// Columns of CSV files, by default Google and Outlook exports are
+    /// recognized.
+    pub csv_columns: Option<ColumnMapping>,
+    /// Credentials for CardDAV address books.
+    pub carddav_username: Option<String>,
+    pub carddav_password: Option<String>,
+    /// Directory for the local copies of CardDAV address books. Without one
+    /// they are downloaded completely every time.
+    pub cache_dir: Option<PathBuf>,
+    /// Identity file to decrypt age encrypted files with.
+    pub age_identity: Option<PathBuf>,
+    /// Command decrypting OpenPGP encrypted files, by default
+    /// [`decrypt::GPG_COMMAND`].
+    pub decrypt_command: Option<String>,
+    /// How long commands may run, by default [`plugin::DEFAULT_TIMEOUT`].
+    pub command_timeout: Option<Duration>,
+}
+
+/// vCards read from all inputs, together with the problems found on the way.
+#[derive(Debug, Default)]
+pub struct Contacts {
+    pub vcards: Vec<VCard>,
+    pub diagnostics: Vec<Diagnostic>,
+    /// Files read so far, each file is read once even if several sources
+    /// include it.
+    read_files: HashSet<PathBuf>,
+}
+
+impl Contacts {
+    /// Adds the cards parsed from `path`, the skipped cards are reported as
+    /// diagnostics.
+    pub fn extend(&mut self, path: &Path, parsed: ParsedVCards) {
+        self.vcards.extend(parsed.vcards);
+        self.diagnostics
+            .extend(parsed.diagnostics.into_iter().map(|e| Diagnostic {
+                path: path.to_path_buf(),
+                error: SourceError::VCardError(e),
+            }));
+    }
+}
+
+/// A problem with one input, the input is skipped (or for invalid vCards
+/// only the card).
+#[derive(Debug)]
+pub struct Diagnostic {
+    /// The file, directory or glob pattern the problem was found in.
+    pub path: PathBuf,
+    pub error: SourceError,
+}
+
+#[derive(Debug)]
+pub enum SourceError {
+    IoError(io::Error),
+    PatternError(glob::PatternError),
+    VCardError(VCardError),
+    /// The address book couldn't be synced, the cached cards were read if
+    /// possible.
+    CardDavError(CardDavError),
+    ThunderbirdError(ThunderbirdError),
+    DecryptError(DecryptError),
+    PluginError(PluginError),
+    /// A problem of a source of an embedding application.
+    Other(Box<dyn error::Error + Send + Sync>),
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        let path = self.path.display();
+        match &self.error {
+            SourceError::IoError(e) => write!(f, "Problem reading {}: {}", path, e),
+            SourceError::PatternError(e) => write!(f, "Invalid pattern {}: {}", path, e),
+            SourceError::VCardError(e) => write!(f, "Skipped invalid vcard in {}: {}", path, e),
+            SourceError::CardDavError(e) => write!(f, "Problem syncing {}: {}", path, e),
+            SourceError::ThunderbirdError(e) => write!(f, "Problem reading {}: {}", path, e),
+            SourceError::DecryptError(e) => write!(f, "Problem decrypting {}: {}", path, e),
+            SourceError::PluginError(e) => write!(f, "Problem running {}: {}", path, e),
+            SourceError::Other(e) => write!(f, "Problem reading {}: {}", path, e),
+        }
+    }
+}
+
+impl error::Error for Diagnostic {}
+
+impl From<io::Error> for SourceError {
+    fn from(e: io::Error) -> Self {
+        Self::IoError(e)
+    }
+}
+
+impl From<DecryptError> for SourceError {
+    fn from(e: DecryptError) -> Self {
+        Self::DecryptError(e)
+    }
+}
+
+impl From<PluginError> for SourceError {
+    fn from(e: PluginError) -> Self {
+        Self::PluginError(e)
+    }
+}
+
+/// Whether the file is a vCard, jCard, xCard, CSV, iCalendar or LDIF file, a
+/// Thunderbird address book or an archive of them, possibly compressed or
+/// encrypted, judging by its extension.
+pub fn is_contact_file(path: &Path) -> bool {
+    if has_extension(path, &WRAPPER_EXTENSIONS) {
+        return is_contact_file(&path.with_extension(""));
+    }
+    has_extension(
+        path,
+        &[
+            VCARD_EXTENSION,
+            CSV_EXTENSION,
+            ICS_EXTENSION,
+            LDIF_EXTENSION,
+            THUNDERBIRD_EXTENSION,
+            ZIP_EXTENSION,
+        ],
+    ) || has_extension(path, &JCARD_EXTENSIONS)
+        || has_extension(path, &XCARD_EXTENSIONS)
+}
+
+/// Whether `input` is a glob pattern rather than a path.
+pub fn is_pattern(input: &str) -> bool {
+    input.contains(['*', '?', '['])
+}
+
+/// Somewhere contacts are read from, e.g. a file or an address book.
+/// Embedding applications can combine their own sources with the built-in
+/// ones in a [`Registry`].
+pub trait ContactSource {
+    /// Adds the contacts of this source, problems are added as diagnostics,
+    /// e.g. as [`SourceError::Other`] for errors of other sources.
+    fn read_contacts(&self, contacts: &mut Contacts);
+}
+
+/// A file in any of the supported formats.
+#[derive(Debug, Clone)]
+pub struct FileSource {
+    pub path: PathBuf,
+    pub options: ReadOptions,
+}
+
+/// The `.vcf` files in a directory and its subdirectories, e.g. a vdir as
+/// synced by vdirsyncer.
+#[derive(Debug, Clone)]
+pub struct DirectorySource {
+    pub path: PathBuf,
+    pub options: ReadOptions,
+}
+
+/// The files matching a glob pattern, matched directories are searched like
+/// by [`DirectorySource`].
+#[derive(Debug, Clone)]
+pub struct PatternSource {
+    pub pattern: String,
+    pub options: ReadOptions,
+}
+
+/// Stdin, e.g. piped from `khard export`.
+#[derive(Debug, Clone)]
+pub struct StdinSource {
+    pub options: ReadOptions,
+}
+
+/// The output of an external command, read like a file unless it's JSON.
+#[derive(Debug, Clone)]
+pub struct PluginSource {
+    pub plugin: Plugin,
+    pub options: ReadOptions,
+}
+
+/// A CardDAV address book without a cache directory.
+#[derive(Debug, Clone)]
+struct UncachedAddressBook {
+    url: String,
+    options: ReadOptions,
+}
+
+/// Sources read one after another, e.g. all inputs of the configuration.
+#[derive(Default)]
+pub struct Registry {
+    sources: Vec<Box<dyn ContactSource>>,
+}
+
+impl ContactSource for FileSource {
+    fn read_contacts(&self, contacts: &mut Contacts) {
+        read_file(&self.path, &self.options, contacts);
+    }
+}
+
+impl ContactSource for DirectorySource {
+    fn read_contacts(&self, contacts: &mut Contacts) {
+        let mut files = Vec::new();
+        add_path(self.path.clone(), &mut files, &mut contacts.diagnostics);
+        for file in files {
+            read_file(&file, &self.options, contacts);
+        }
+    }
+}
+
+impl ContactSource for PatternSource {
+    fn read_contacts(&self, contacts: &mut Contacts) {
+        let paths = match glob::glob(&self.pattern) {
+            Ok(paths) => paths,
+            Err(e) => {
+                contacts.diagnostics.push(Diagnostic {
+                    path: PathBuf::from(&self.pattern),
+                    error: SourceError::PatternError(e),
+                });
+                return;
+            }
+        };
+        let mut files = Vec::new();
+        for path in paths {
+            match path {
+                Ok(path) => add_path(path, &mut files, &mut contacts.diagnostics),
+                Err(e) => contacts.diagnostics.push(Diagnostic {
+                    path: e.path().to_path_buf(),
+                    error: SourceError::IoError(e.into()),
+                }),
+            }
+        }
+        for file in files {
+            read_file(&file, &self.options, contacts);
+        }
+    }
+}
+
+impl ContactSource for StdinSource {
+    fn read_contacts(&self, contacts: &mut Contacts) {
+        let path = Path::new(STDIN);
+        if let Err(error) = read_contents(path, &mut io::stdin().lock(), &self.options, contacts) {
+            contacts.diagnostics.push(Diagnostic {
+                path: path.to_path_buf(),
+                error,
+            });
+        }
+    }
+}
+
+/// Syncs the address book, or reads the cached cards if that fails.
+impl ContactSource for AddressBook {
+    fn read_contacts(&self, contacts: &mut Contacts) {
+        let path = Path::new(&self.url);
+        let parsed = self.sync().or_else(|e| {
+            contacts.diagnostics.push(Diagnostic {
+                path: path.to_path_buf(),
+                error: SourceError::CardDavError(e),
+            });
+            self.read_cache()
+        });
+        // if the cache is as unreadable as the server, that was reported already
+        if let Ok(parsed) = parsed {
+            contacts.extend(path, parsed);
+        }
+    }
+}
+
+/// Reads the address book into a private temporary directory, which is
+/// removed again right away.
+impl ContactSource for UncachedAddressBook {
+    fn read_contacts(&self, contacts: &mut Contacts) {
+        let path = Path::new(&self.url);
+        contacts.diagnostics.push(Diagnostic {
+            path: path.to_path_buf(),
+            error: SourceError::CardDavError(CardDavError::NoCacheError),
+        });
+        match tempfile::Builder::new().prefix("remember-bday-").tempdir() {
+            Ok(dir) => address_book(&self.url, dir.path(), &self.options).read_contacts(contacts),
+            Err(e) => contacts.diagnostics.push(Diagnostic {
+                path: path.to_path_buf(),
+                error: SourceError::IoError(e),
+            }),
+        }
+    }
+}
+
+impl ContactSource for PluginSource {
+    fn read_contacts(&self, contacts: &mut Contacts) {
+        let input = format!("{}{}", plugin::PREFIX, self.plugin.command);
+        let path = Path::new(&input);
+        let result = self
+            .plugin
+            .run()
+            .map_err(SourceError::from)
+            .and_then(|output| {
+                if plugin::is_json(&output) {
+                    contacts.extend(path, plugin::parse_contacts(&output)?);
+                    Ok(())
+                } else {
+                    read_contents(path, &mut output.as_slice(), &self.options, contacts)
+                }
+            });
+        if let Err(error) = result {
+            contacts.diagnostics.push(Diagnostic {
+                path: path.to_path_buf(),
+                error,
+            });
+        }
+    }
+}
+
+impl ContactSource for Registry {
+    fn read_contacts(&self, contacts: &mut Contacts) {
+        for source in &self.sources {
+            source.read_contacts(contacts);
+        }
+    }
+}
+
+impl Registry {
+    /// The sources of all inputs, which are files, directories (searched
+    /// recursively for `.vcf` files), glob patterns, URLs of CardDAV address
+    /// books, commands like `exec:crm-export` or `-` for stdin.
+    pub fn from_inputs(inputs: &[String], options: &ReadOptions) -> Self {
+        let mut registry = Registry::default();
+        for input in inputs {
+            let options = options.clone();
+            if carddav::is_url(input) {
+                match &options.cache_dir {
+                    Some(cache_dir) => registry.add(address_book(input, cache_dir, &options)),
+                    None => registry.add(UncachedAddressBook {
+                        url: input.clone(),
+                        options,
+                    }),
+                }
+            } else if plugin::is_command(input) {
+                let timeout = options.command_timeout.unwrap_or(plugin::DEFAULT_TIMEOUT);
+                registry.add(PluginSource {
+                    plugin: Plugin::new(input, timeout),
+                    options,
+                });
+            } else if input == STDIN {
+                registry.add(StdinSource { options });
+            } else if is_pattern(input) {
+                registry.add(PatternSource {
+                    pattern: input.clone(),
+                    options,
+                });
+            } else if Path::new(input).is_dir() {
+                registry.add(DirectorySource {
+                    path: PathBuf::from(input),
+                    options,
+                });
+            } else {
+                registry.add(FileSource {
+                    path: PathBuf::from(input),
+                    options,
+                });
+            }
+        }
+        registry
+    }
+
+    pub fn add(&mut self, source: impl ContactSource + 'static) {
+        self.sources.push(Box::new(source));
+    }
+}
+
+/// Reads the vCards of all inputs, see [`Registry::from_inputs`].
+///
+/// The format of a file is recognized by its content, only CSV files need
+/// their extension.
+pub fn read_vcards(inputs: &[String], options: &ReadOptions) -> Contacts {
+    let mut contacts = Contacts::default();
+    Registry::from_inputs(inputs, options).read_contacts(&mut contacts);
+    contacts
+}
+
+fn add_path(path: PathBuf, files: &mut Vec<PathBuf>, diagnostics: &mut Vec<Diagnostic>) {
+    if !path.is_dir() {
+        // missing files are reported when reading them
+        files.push(path);
+        return;
+    }
+
+    let entries = fs::read_dir(&path).and_then(|entries| {
+        entries
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<io::Result<Vec<_>>>()
+    });
+    let mut paths = match entries {
+        Ok(paths) => paths,
+        Err(e) => {
+            diagnostics.push(Diagnostic {
+                path,
+                error: SourceError::IoError(e),
+            });
+            return;
+        }
+    };
+    // the order of read_dir is platform dependent
+    paths.sort();
+    for path in paths {
+        if path.is_dir() || has_extension(&path, &[VCARD_EXTENSION]) {
+            add_path(path, files, diagnostics);
+        }
+    }
+}
+
+fn has_extension(path: &Path, extensions: &[&str]) -> bool {
+    path.extension().is_some_and(|extension| {
+        extensions
+            .iter()
+            .any(|expected| extension.eq_ignore_ascii_case(expected))
+    })
+}
+
+/// The address book at `url`, cached in a directory of its own in
+/// `cache_dir`.
+fn address_book(url: &str, cache_dir: &Path, options: &ReadOptions) -> AddressBook {
+    // a directory per address book, e.g. "cloud.example.com_dav_contacts"
+    let name: String = url
+        .split_once("://")
+        .map_or(url, |(_, rest)| rest)
+        .trim_end_matches('/')
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '.' || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    AddressBook {
+        url: String::from(url),
+        username: options.carddav_username.clone(),
+        password: options.carddav_password.clone(),
+        cache_dir: cache_dir.join("carddav").join(name),
+    }
+}
+
+fn read_file(path: &Path, options: &ReadOptions, contacts: &mut Contacts) {
+    // the same file may be given differently, e.g. as `./a.vcf` and `a.vcf`
+    let canonical = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    if !contacts.read_files.insert(canonical) {
+        return;
+    }
+    if let Err(error) = try_read_file(path, options, contacts) {
+        contacts.diagnostics.push(Diagnostic {
+            path: path.to_path_buf(),
+            error,
+        });
+    }
+}
+
+fn try_read_file(
+    path: &Path,
+    options: &ReadOptions,
+    contacts: &mut Contacts,
+) -> Result<(), SourceError> {
+    let mut reader = BufReader::new(File::open(path)?);
+    if reader.fill_buf()?.starts_with(SQLITE_MAGIC) {
+        // SQLite reads the file itself
+        read_thunderbird_address_book(path, contacts);
+        return Ok(());
+    }
+    read_contents(path, &mut reader, options, contacts)
+}
+
+/// Reads the contact files in the archive, judging them by their extension.
+fn read_zip(
+    path: &Path,
+    reader: &mut dyn BufRead,
+    options: &ReadOptions,
+    contacts: &mut Contacts,
+) -> io::Result<()> {
+    // the directory of a zip archive is at its end
+    let mut contents = Vec::new();
+    reader.read_to_end(&mut contents)?;
+    let mut archive = ZipArchive::new(Cursor::new(contents))?;
+    for index in 0..archive.len() {
+        let entry = archive.by_index(index)?;
+        let entry_path = path.join(entry.name());
+        if entry.is_dir() || !is_contact_file(&entry_path) {
+            continue;
+        }
+        if let Err(error) =
+            read_contents(&entry_path, &mut BufReader::new(entry), options, contacts)
+        {
+            contacts.diagnostics.push(Diagnostic {
+                path: entry_path,
+                error,
+            });
+        }
+    }
+    Ok(())
+}
+
+fn read_thunderbird_address_book(path: &Path, contacts: &mut Contacts) {
+    match thunderbird::read_address_book(path) {
+        Ok(parsed) => contacts.extend(path, parsed),
+        Err(e) => contacts.diagnostics.push(Diagnostic {
+            path: path.to_path_buf(),
+            error: SourceError::ThunderbirdError(e),
+        }),
+    }
+}
+
+/// Reads the contacts of a file, `path` is only used to judge its format
+/// and to report problems. Encrypted files are decrypted in memory only.
+fn read_contents(
+    path: &Path,
+    reader: &mut dyn BufRead,
+    options: &ReadOptions,
+    contacts: &mut Contacts,
+) -> Result<(), SourceError> {
+    let start = reader.fill_buf()?;
+    if start.starts_with(GZIP_MAGIC) {
+        return read_contents(
+            path,
+            &mut BufReader::new(MultiGzDecoder::new(reader)),
+            options,
+            contacts,
+        );
+    } else if start.starts_with(ZIP_MAGIC) {
+        return Ok(read_zip(path, reader, options, contacts)?);
+    } else if start.starts_with(SQLITE_MAGIC) {
+        return Err(SourceError::IoError(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Thunderbird address books can only be read from a file",
+        )));
+    } else if start.starts_with(AGE_MAGIC) || start.starts_with(AGE_ARMOR_MAGIC) {
+        let identity = options
+            .age_identity
+            .as_deref()
+            .ok_or(DecryptError::MissingIdentityError)?;
+        let plaintext = decrypt::decrypt_age(&read_all(reader)?, identity)?;
+        return read_contents(path, &mut plaintext.as_slice(), options, contacts);
+    } else if start.starts_with(PGP_ARMOR_MAGIC)
+        || (is_openpgp_message(start)
+            && (has_extension(path, &OPENPGP_EXTENSIONS) || format_by_extension(path).is_none()))
+    {
+        let command = options
+            .decrypt_command
+            .as_deref()
+            .unwrap_or(decrypt::GPG_COMMAND);
+        let timeout = options.command_timeout.unwrap_or(plugin::DEFAULT_TIMEOUT);
+        let plaintext = decrypt::decrypt_with_command(&read_all(reader)?, command, timeout)?;
+        return read_contents(path, &mut plaintext.as_slice(), options, contacts);
+    }
+
+    let format = match format_by_extension(path) {
+        Some(format) => format,
+        None => sniff_format(reader)?,
+    };
+    let results: Box<dyn Iterator<Item = Result<VCard, VCardError>>> = match format {
+        Format::Text => Box::new(VCardReader::new(reader)),
+        Format::Json | Format::Xml | Format::Csv | Format::Ics | Format::Ldif => {
+            let contents = read_all(reader)?;
+            let parsed = match format {
+                Format::Json => vcard::parse_jcards_lenient(contents),
+                Format::Xml => vcard::parse_xcards_lenient(contents),
+                Format::Csv => vcard::parse_csv(contents, options.csv_columns.as_ref()),
+                Format::Ldif => vcard::parse_ldif(contents),
+                _ => ical::parse_ics(contents),
+            };
+            Box::new(
+                parsed
+                    .vcards
+                    .into_iter()
+                    .map(Ok)
+                    .chain(parsed.diagnostics.into_iter().map(Err)),
+            )
+        }
+    };
+
+    for result in results {
+        match result {
+            Ok(vcard) => contacts.vcards.push(vcard),
+            Err(e) => contacts.diagnostics.push(Diagnostic {
+                path: path.to_path_buf(),
+                error: SourceError::VCardError(e),
+            }),
+        }
+    }
+    Ok(())
+}
+
+fn read_all(reader: &mut dyn BufRead) -> io::Result<Vec<u8>> {
+    let mut contents = Vec::new();
+    reader.read_to_end(&mut contents)?;
+    Ok(contents)
+}
+
+/// Whether a binary OpenPGP message starts here, i.e. with a packet holding
+/// the session key encrypted to a public key or with a passphrase (RFC 4880,
+/// section 4.2). Their tags are 1 and 3, in the old or new packet format,
+/// and the version after the length must be a known one, as a single byte
+/// like `0xc3` also starts text, e.g. `Ä` in UTF-8.
+fn is_openpgp_message(start: &[u8]) -> bool {
+    let Some(&tag_byte) = start.first() else {
+        return false;
+    };
+    let (tag, length_bytes) = match tag_byte & 0xc0 {
+        // old format, the length type is in the lowest bits
+        0x80 => match tag_byte & 0x03 {
+            3 => return false,
+            length_type => ((tag_byte >> 2) & 0x0f, 1 << length_type),
+        },
+        0xc0 => match start.get(1) {
+            Some(0..=191) => (tag_byte & 0x3f, 1),
+            Some(192..=223) => (tag_byte & 0x3f, 2),
+            Some(255) => (tag_byte & 0x3f, 5),
+            // partial lengths aren't allowed for these packets
+            _ => return false,
+        },
+        _ => return false,
+    };
+    let version = start.get(1 + length_bytes);
+    match tag {
+        1 => matches!(version, Some(3 | 6)),
+        3 => matches!(version, Some(4..=6)),
+        _ => false,
+    }
+}
+
+/// The format of text formats that are named by their extension, an
+/// extension like `.gz` or `.age` is skipped.
+fn format_by_extension(path: &Path) -> Option<Format> {
+    if has_extension(path, &WRAPPER_EXTENSIONS) {
+        return format_by_extension(&path.with_extension(""));
+    }
+    Some(if has_extension(path, &[CSV_EXTENSION]) {
+        Format::Csv
+    } else if has_extension(path, &[ICS_EXTENSION]) {
+        Format::Ics
+    } else if has_extension(path, &[LDIF_EXTENSION]) {
+        Format::Ldif
+    } else if has_extension(path, &JCARD_EXTENSIONS) {
+        Format::Json
+    } else if has_extension(path, &XCARD_EXTENSIONS) {
+        Format::Xml
+    } else {
+        return None;
+    })
+}
+
+enum Format {
+    Text,
+    Json,
+    Xml,
+    Csv,
+    Ics,
+    Ldif,
+}
+
+/// Guesses the format from the start of the content: `[` for a jCard, `<`
+/// for an xCard, `BEGIN:VCALENDAR` for an iCalendar, `dn:` or `version:` for
+/// LDIF, otherwise `BEGIN:VCARD` is expected.
+fn sniff_format(reader: &mut dyn BufRead) -> io::Result<Format> {
+    let start = reader.fill_buf()?;
+    let start = match start.iter().position(|b| !b.is_ascii_whitespace()) {
+        Some(position) => &start[position..],
+        None => return Ok(Format::Text),
+    };
+    let starts_with = |prefix: &[u8]| {
+        start.len() >= prefix.len() && start[..prefix.len()].eq_ignore_ascii_case(prefix)
+    };
+    Ok(match start[0] {
+        b'[' => Format::Json,
+        b'<' => Format::Xml,
+        _ if starts_with(b"BEGIN:VCALENDAR") => Format::Ics,
+        _ if starts_with(b"dn:") || starts_with(b"version:") => Format::Ldif,
+        _ => Format::Text,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vcard::{DateAndOrTime, ErrorKind};
+    use age::secrecy::ExposeSecret;
+    use chrono::NaiveDate;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use std::io::Write;
+    use zip::write::SimpleFileOptions;
+    use zip::ZipWriter;
+
+    fn write_card(dir: &Path, file: &str, name: &str) {
+        let path = dir.join(file);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(
+            path,
+            format!("BEGIN:VCARD\r\nVERSION:3.0\r\nFN:{}\r\nEND:VCARD\r\n", name),
+        )
+        .unwrap();
+    }
+
+    fn names(contacts: &Contacts) -> Vec<&str> {
+        contacts
+            .vcards
+            .iter()
+            .map(|vcard| vcard.name.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn read_directories_recursively() {
+        let dir = tempfile::tempdir().unwrap();
+        write_card(dir.path(), "contacts/b.vcf", "Bob Test");
+        write_card(dir.path(), "contacts/a.VCF", "Allice Test");
+        write_card(dir.path(), "contacts/nested/c.vcf", "Charlie Test");
+        write_card(dir.path(), "contacts/notes.txt", "Not a contact");
+        write_card(dir.path(), "export.vcf", "Dave Test");
+
+        let contacts = read_vcards(
+            &[
+                dir.path().join("contacts").display().to_string(),
+                dir.path().join("export.vcf").display().to_string(),
+            ],
+            &ReadOptions::default(),
+        );
+
+        assert!(
+            contacts.diagnostics.is_empty(),
+            "{:?}",
+            contacts.diagnostics
+        );
+        assert_eq!(
+            vec!["Allice Test", "Bob Test", "Charlie Test", "Dave Test"],
+            names(&contacts)
+        );
+    }
+
+    #[test]
+    fn read_glob_patterns() {
+        let dir = tempfile::tempdir().unwrap();
+        write_card(dir.path(), "work/a.vcf", "Allice Test");
+        write_card(dir.path(), "private/b.vcf", "Bob Test");
+        write_card(dir.path(), "private/b.bak", "Old Bob Test");
+
+        let pattern = dir.path().join("*").join("*.vcf").display().to_string();
+        // files matched twice are only read once
+        let file = dir.path().join("work/a.vcf").display().to_string();
+        let contacts = read_vcards(&[pattern, file], &ReadOptions::default());
+
+        assert!(
+            contacts.diagnostics.is_empty(),
+            "{:?}",
+            contacts.diagnostics
+        );
+        assert_eq!(vec!["Bob Test", "Allice Test"], names(&contacts));
+    }
+
+    #[test]
+    fn report_file_of_errors() {
+        let dir = tempfile::tempdir().unwrap();
+        write_card(dir.path(), "a.vcf", "Allice Test");
+        let broken = dir.path().join("broken.vcf");
+        fs::write(&broken, "BEGIN:VCARD\r\nVERSION:3.0\r\nEND:VCARD\r\n").unwrap();
+        let missing = dir.path().join("missing.vcf");
+
+        let contacts = read_vcards(
+            &[
+                dir.path().display().to_string(),
+                missing.display().to_string(),
+            ],
+            &ReadOptions::default(),
+        );
+
+        assert_eq!(vec!["Allice Test"], names(&contacts));
+        assert_eq!(2, contacts.diagnostics.len());
+        assert_eq!(broken, contacts.diagnostics[0].path);
+        assert!(matches!(
+            &contacts.diagnostics[0].error,
+            SourceError::VCardError(e) if e.kind == ErrorKind::NoNameError
+        ));
+        assert_eq!(missing, contacts.diagnostics[1].path);
+        assert!(matches!(
+            &contacts.diagnostics[1].error,
+            SourceError::IoError(e) if e.kind() == io::ErrorKind::NotFound
+        ));
+        assert!(contacts.diagnostics[0]
+            .to_string()
+            .starts_with(&format!("Skipped invalid vcard in {}: ", broken.display())));
+    }
+
+    #[test]
+    fn read_jcards() {
+        let dir = tempfile::tempdir().unwrap();
+        let jcard = r#"["vcard", [["fn", {}, "text", "Allice Test"]]]"#;
+        fs::write(dir.path().join("a.jcard"), jcard).unwrap();
+        // sniffed from the content
+        fs::write(
+            dir.path().join("export.txt"),
+            format!(" \n[{}, {}]", jcard, jcard.replace("Allice", "Bob")),
+        )
+        .unwrap();
+
+        let contacts = read_vcards(
+            &[
+                dir.path().join("a.jcard").display().to_string(),
+                dir.path().join("export.txt").display().to_string(),
+            ],
+            &ReadOptions::default(),
+        );
+
+        assert!(
+            contacts.diagnostics.is_empty(),
+            "{:?}",
+            contacts.diagnostics
+        );
+        assert_eq!(
+            vec!["Allice Test", "Allice Test", "Bob Test"],
+            names(&contacts)
+        );
+    }
+
+    #[test]
+    fn read_xcards() {
+        let dir = tempfile::tempdir().unwrap();
+        let xcard = r#"<vcards xmlns="urn:ietf:params:xml:ns:vcard-4.0">
+  <vcard><fn><text>Allice Test</text></fn></vcard>
+</vcards>"#;
+        fs::write(dir.path().join("a.xml"), xcard).unwrap();
+        // sniffed from the content
+        fs::write(
+            dir.path().join("export.txt"),
+            format!(
+                "<?xml version=\"1.0\"?>\n{}",
+                xcard.replace("Allice", "Bob")
+            ),
+        )
+        .unwrap();
+
+        let contacts = read_vcards(
+            &[
+                dir.path().join("a.xml").display().to_string(),
+                dir.path().join("export.txt").display().to_string(),
+            ],
+            &ReadOptions::default(),
+        );
+
+        assert!(
+            contacts.diagnostics.is_empty(),
+            "{:?}",
+            contacts.diagnostics
+        );
+        assert_eq!(vec!["Allice Test", "Bob Test"], names(&contacts));
+    }
+
+    #[test]
+    fn read_csv() {
+        let dir = tempfile::tempdir().unwrap();
+        let csv = dir.path().join("contacts.csv");
+        fs::write(&csv, "Full Name,Date of Birth\nAllice Test,07.05.1980\n").unwrap();
+        let options = ReadOptions {
+            csv_columns: Some(
+                "name=Full Name;birthday=Date of Birth;date_format=%d.%m.%Y"
+                    .parse()
+                    .unwrap(),
+            ),
+            ..Default::default()
+        };
+
+        let contacts = read_vcards(&[csv.display().to_string()], &options);
+
+        assert!(
+            contacts.diagnostics.is_empty(),
+            "{:?}",
+            contacts.diagnostics
+        );
+        assert_eq!(vec!["Allice Test"], names(&contacts));
+        assert_eq!(
+            NaiveDate::from_ymd_opt(1980, 5, 7).map(DateAndOrTime::Date),
+            contacts.vcards[0].bday
+        );
+    }
+
+    #[test]
+    fn read_ics() {
+        let dir = tempfile::tempdir().unwrap();
+        let ics = "BEGIN:VCALENDAR\r\nBEGIN:VEVENT\r\nDTSTART;VALUE=DATE:19800507\r\n\
+            RRULE:FREQ=YEARLY\r\nSUMMARY:Allice Test's birthday\r\nEND:VEVENT\r\nEND:VCALENDAR\r\n";
+        fs::write(dir.path().join("birthdays.ics"), ics).unwrap();
+        // sniffed from the content
+        fs::write(
+            dir.path().join("calendar.txt"),
+            ics.replace("Allice", "Bob"),
+        )
+        .unwrap();
+
+        let contacts = read_vcards(
+            &[
+                dir.path().join("birthdays.ics").display().to_string(),
+                dir.path().join("calendar.txt").display().to_string(),
+            ],
+            &ReadOptions::default(),
+        );
+
+        assert!(
+            contacts.diagnostics.is_empty(),
+            "{:?}",
+            contacts.diagnostics
+        );
+        assert_eq!(vec!["Allice Test", "Bob Test"], names(&contacts));
+    }
+
+    #[test]
+    fn read_ldif() {
+        let dir = tempfile::tempdir().unwrap();
+        let ldif = dir.path().join("abook.ldif");
+        fs::write(
+            &ldif,
+            "dn: cn=Allice Test\ncn: Allice Test\nbirthyear: 1980\nbirthmonth: 5\nbirthday: 7\n",
+        )
+        .unwrap();
+
+        let contacts = read_vcards(&[ldif.display().to_string()], &ReadOptions::default());
+
+        assert!(
+            contacts.diagnostics.is_empty(),
+            "{:?}",
+            contacts.diagnostics
+        );
+        assert_eq!(vec!["Allice Test"], names(&contacts));
+        assert_eq!(
+            NaiveDate::from_ymd_opt(1980, 5, 7).map(DateAndOrTime::Date),
+            contacts.vcards[0].bday
+        );
+    }
+
+    #[test]
+    fn read_thunderbird() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("abook.sqlite");
+        rusqlite::Connection::open(&path)
+            .unwrap()
+            .execute_batch(
+                "CREATE TABLE properties (card TEXT, name TEXT, value TEXT);
+                INSERT INTO properties VALUES ('a', 'DisplayName', 'Allice Test');",
+            )
+            .unwrap();
+
+        let contacts = read_vcards(&[path.display().to_string()], &ReadOptions::default());
+
+        assert!(
+            contacts.diagnostics.is_empty(),
+            "{:?}",
+            contacts.diagnostics
+        );
+        assert_eq!(vec!["Allice Test"], names(&contacts));
+    }
+
+    fn gzip(contents: &str) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(contents.as_bytes()).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn read_gzip() {
+        let dir = tempfile::tempdir().unwrap();
+        let vcf = dir.path().join("contacts.vcf.gz");
+        // concatenated like by `cat a.vcf.gz b.vcf.gz`
+        let mut contents = gzip("BEGIN:VCARD\r\nVERSION:4.0\r\nFN:Allice Test\r\nEND:VCARD\r\n");
+        contents.extend(gzip(
+            "BEGIN:VCARD\r\nVERSION:4.0\r\nFN:Carl Test\r\nEND:VCARD\r\n",
+        ));
+        fs::write(&vcf, contents).unwrap();
+        // the format is judged by the name without .gz
+        let csv = dir.path().join("contacts.csv.gz");
+        fs::write(
+            &csv,
+            gzip("First Name,Last Name,Birthday\nBob,Test,1981-06-08\n"),
+        )
+        .unwrap();
+
+        let contacts = read_vcards(
+            &[vcf.display().to_string(), csv.display().to_string()],
+            &ReadOptions::default(),
+        );
+
+        assert!(
+            contacts.diagnostics.is_empty(),
+            "{:?}",
+            contacts.diagnostics
+        );
+        assert_eq!(
+            vec!["Allice Test", "Carl Test", "Bob Test"],
+            names(&contacts)
+        );
+    }
+
+    #[test]
+    fn read_zip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("takeout.zip");
+        let mut archive = ZipWriter::new(File::create(&path).unwrap());
+        let options = SimpleFileOptions::default();
+        archive.add_directory("Takeout/Contacts/", options).unwrap();
+        archive
+            .start_file("Takeout/Contacts/All Contacts.vcf", options)
+            .unwrap();
+        archive
+            .write_all(b"BEGIN:VCARD\r\nVERSION:4.0\r\nFN:Allice Test\r\nEND:VCARD\r\n")
+            .unwrap();
+        archive
+            .start_file("Takeout/archive_browser.html", options)
+            .unwrap();
+        archive.write_all(b"<html></html>").unwrap();
+        archive
+            .start_file("Takeout/Contacts/Broken.vcf", options)
+            .unwrap();
+        archive.write_all(b"BEGIN:VCARD\r\nFN:\r\n").unwrap();
+        archive.finish().unwrap();
+
+        let contacts = read_vcards(&[path.display().to_string()], &ReadOptions::default());
+
+        assert_eq!(vec!["Allice Test"], names(&contacts));
+        assert_eq!(1, contacts.diagnostics.len());
+        assert_eq!(
+            path.join("Takeout/Contacts/Broken.vcf"),
+            contacts.diagnostics[0].path
+        );
+    }
+
+    #[test]
+    fn read_by_content() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("contacts.bak");
+        fs::write(
+            &path,
+            gzip("dn: cn=Allice Test\ncn: Allice Test\nbirthDate: 1980-05-07\n"),
+        )
+        .unwrap();
+
+        let contacts = read_vcards(&[path.display().to_string()], &ReadOptions::default());
+
+        assert!(
+            contacts.diagnostics.is_empty(),
+            "{:?}",
+            contacts.diagnostics
+        );
+        assert_eq!(vec!["Allice Test"], names(&contacts));
+    }
+
+    #[test]
+    fn read_age_encrypted() {
+        let dir = tempfile::tempdir().unwrap();
+        let identity = age::x25519::Identity::generate();
+        let identity_file = dir.path().join("key.txt");
+        fs::write(&identity_file, identity.to_string().expose_secret()).unwrap();
+        let path = dir.path().join("contacts.csv.age");
+        let csv = "First Name,Last Name,Birthday\nAllice,Test,1980-05-07\n";
+        fs::write(
+            &path,
+            age::encrypt(&identity.to_public(), csv.as_bytes()).unwrap(),
+        )
+        .unwrap();
+
+        let contacts = read_vcards(&[path.display().to_string()], &ReadOptions::default());
+
+        assert!(contacts.vcards.is_empty());
+        assert!(matches!(
+            contacts.diagnostics[0].error,
+            SourceError::DecryptError(DecryptError::MissingIdentityError)
+        ));
+
+        let options = ReadOptions {
+            age_identity: Some(identity_file),
+            ..Default::default()
+        };
+        let contacts = read_vcards(&[path.display().to_string()], &options);
+
+        assert!(
+            contacts.diagnostics.is_empty(),
+            "{:?}",
+            contacts.diagnostics
+        );
+        assert_eq!(vec!["Allice Test"], names(&contacts));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn read_openpgp_encrypted() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("contacts.asc");
+        // decrypted by dropping the armor lines, instead of gpg
+        fs::write(
+            &path,
+            "-----BEGIN PGP MESSAGE-----\nBEGIN:VCARD\r\nVERSION:4.0\r\nFN:Allice Test\r\n\
+            END:VCARD\r\n-----END PGP MESSAGE-----\n",
+        )
+        .unwrap();
+        let options = ReadOptions {
+            decrypt_command: Some(String::from("grep -v PGP")),
+            ..Default::default()
+        };
+
+        let contacts = read_vcards(&[path.display().to_string()], &options);
+
+        assert!(
+            contacts.diagnostics.is_empty(),
+            "{:?}",
+            contacts.diagnostics
+        );
+        assert_eq!(vec!["Allice Test"], names(&contacts));
+    }
+
+    #[test]
+    fn sniff_openpgp_messages() {
+        // a key ID and algorithm would follow the version
+        assert!(is_openpgp_message(b"\x85\x01\x0c\x03"));
+        assert!(is_openpgp_message(b"\xc1\x0c\x03"));
+        assert!(is_openpgp_message(b"\x8c\x0d\x04\x09"));
+        assert!(is_openpgp_message(b"\xc3\x0d\x04\x09"));
+
+        assert!(!is_openpgp_message("\u{c4}nderung".as_bytes()));
+        assert!(!is_openpgp_message("\u{c9}ch\u{e9}ance".as_bytes()));
+        assert!(!is_openpgp_message(b"\x84nderung"));
+        assert!(!is_openpgp_message(b"\xc3"));
+        assert!(!is_openpgp_message(b""));
+    }
+
+    #[test]
+    fn read_csv_starting_like_openpgp() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("contacts.csv");
+        fs::write(
+            &path,
+            "\u{c4}nderung,First Name,Last Name,Birthday\n2024-01-01,Allice,Test,1980-05-07\n",
+        )
+        .unwrap();
+        let options = ReadOptions {
+            decrypt_command: Some(String::from("false")),
+            ..Default::default()
+        };
+
+        let contacts = read_vcards(&[path.display().to_string()], &options);
+
+        assert!(
+            contacts.diagnostics.is_empty(),
+            "{:?}",
+            contacts.diagnostics
+        );
+        assert_eq!(vec!["Allice Test"], names(&contacts));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn read_plugins() {
+        let inputs = [
+            String::from(
+                r#"exec:echo {"version":1,"contacts":[{"name":"Allice","birthday":"1980-05-07"}]}"#,
+            ),
+            String::from(r"exec:printf BEGIN:VCARD\r\nVERSION:4.0\r\nFN:Bob\r\nEND:VCARD\r\n"),
+            String::from("exec:false"),
+        ];
+
+        let contacts = read_vcards(&inputs, &ReadOptions::default());
+
+        assert_eq!(vec!["Allice", "Bob"], names(&contacts));
+        assert_eq!(1, contacts.diagnostics.len());
+        assert_eq!(Path::new("exec:false"), contacts.diagnostics[0].path);
+        assert!(matches!(
+            contacts.diagnostics[0].error,
+            SourceError::PluginError(PluginError::FailedError(_))
+        ));
+    }
+
+    #[test]
+    fn report_unreachable_address_book() {
+        let cache = tempfile::tempdir().unwrap();
+        let options = ReadOptions {
+            cache_dir: Some(cache.path().to_path_buf()),
+            ..Default::default()
+        };
+
+        // nothing listens on port 9 (discard)
+        let url = "http://127.0.0.1:9/contacts/";
+        let contacts = read_vcards(&[String::from(url)], &options);
+
+        assert!(contacts.vcards.is_empty());
+        assert_eq!(PathBuf::from(url), contacts.diagnostics[0].path);
+        assert!(matches!(
+            contacts.diagnostics[0].error,
+            SourceError::CardDavError(_)
+        ));
+
+        // without a cache nothing is kept after the run
+        let contacts = read_vcards(&[String::from(url)], &ReadOptions::default());
+
+        assert!(contacts.vcards.is_empty());
+        assert!(matches!(
+            contacts.diagnostics[0].error,
+            SourceError::CardDavError(CardDavError::NoCacheError)
+        ));
+        assert!(matches!(
+            contacts.diagnostics[1].error,
+            SourceError::CardDavError(CardDavError::HttpError(_))
+        ));
+    }
+
+    struct FixedSource(&'static str);
+
+    impl ContactSource for FixedSource {
+        fn read_contacts(&self, contacts: &mut Contacts) {
+            contacts.vcards.push(VCard {
+                name: String::from(self.0),
+                ..Default::default()
+            });
+        }
+    }
+
+    #[test]
+    fn combine_sources() {
+        let dir = tempfile::tempdir().unwrap();
+        write_card(dir.path(), "a.vcf", "Allice Test");
+        let options = ReadOptions::default();
+
+        let mut registry = Registry::from_inputs(&[dir.path().display().to_string()], &options);
+        registry.add(FixedSource("Bob Test"));
+        // already read by the directory source
+        registry.add(FileSource {
+            path: dir.path().join("a.vcf"),
+            options,
+        });
+        let mut contacts = Contacts::default();
+        registry.read_contacts(&mut contacts);
+
+        assert!(
+            contacts.diagnostics.is_empty(),
+            "{:?}",
+            contacts.diagnostics
+        );
+        assert_eq!(vec!["Allice Test", "Bob Test"], names(&contacts));
+    }
+
+    /// Like an address book of an embedding application that can't be
+    /// reached.
+    struct FailingSource;
+
+    impl ContactSource for FailingSource {
+        fn read_contacts(&self, contacts: &mut Contacts) {
+            let path = Path::new("crm://contacts");
+            contacts.extend(path, vcard::parse_jcards_lenient("[\"vcard\""));
+            contacts.diagnostics.push(Diagnostic {
+                path: path.to_path_buf(),
+                error: SourceError::Other("connection refused".into()),
+            });
+        }
+    }
+
+    #[test]
+    fn report_problems_of_other_sources() {
+        let mut registry = Registry::default();
+        registry.add(FailingSource);
+        registry.add(FixedSource("Bob Test"));
+        let mut contacts = Contacts::default();
+        registry.read_contacts(&mut contacts);
+
+        assert_eq!(vec!["Bob Test"], names(&contacts));
+        assert_eq!(2, contacts.diagnostics.len());
+        assert!(matches!(
+            contacts.diagnostics[0].error,
+            SourceError::VCardError(_)
+        ));
+        assert_eq!(
+            "Problem reading crm://contacts: connection refused",
+            contacts.diagnostics[1].to_string()
+        );
+    }
+
+    #[test]
+    fn read_files_once() {
+        let dir = tempfile::tempdir().unwrap();
+        write_card(dir.path(), "contacts/a.vcf", "Allice Test");
+        let contacts_dir = dir.path().join("contacts");
+
+        let contacts = read_vcards(
+            &[
+                contacts_dir.join("a.vcf").display().to_string(),
+                contacts_dir.join(".").join("a.vcf").display().to_string(),
+                contacts_dir
+                    .join("..")
+                    .join("contacts")
+                    .display()
+                    .to_string(),
+                contacts_dir.join("*.vcf").display().to_string(),
+            ],
+            &ReadOptions::default(),
+        );
+
+        assert!(
+            contacts.diagnostics.is_empty(),
+            "{:?}",
+            contacts.diagnostics
+        );
+        assert_eq!(vec!["Allice Test"], names(&contacts));
+    }
+
+    #[test]
+    fn report_invalid_pattern() {
+        let contacts = read_vcards(&[String::from("contacts/[.vcf")], &ReadOptions::default());
+        assert!(contacts.vcards.is_empty());
+        assert!(matches!(
+            contacts.diagnostics[0].error,
+            SourceError::PatternError(_)
+        ));
+    }
+}
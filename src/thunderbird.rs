@@ -0,0 +1,285 @@
+use crate::fields::{contact_properties, thunderbird_birthday};
+use crate::vcard::{ParsedVCards, VCard, VCardError, VCardReader};
+use rusqlite::{Connection, ErrorCode, OpenFlags};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use std::{error, fmt};
+use tempfile::TempDir;
+
+/// Name of the property holding the whole card since Thunderbird 102.
+const VCARD_PROPERTY: &str = "_vCard";
+
+/// How long to wait for a lock to be released before reading a copy.
+const BUSY_TIMEOUT: Duration = Duration::from_millis(200);
+
+#[derive(Debug)]
+pub enum ThunderbirdError {
+    SqliteError(rusqlite::Error),
+    /// The database was locked and couldn't be copied either.
+    IoError(io::Error),
+}
+
+impl fmt::Display for ThunderbirdError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        match self {
+            Self::SqliteError(e) => write!(f, "invalid address book: {}", e),
+            Self::IoError(e) => write!(f, "locked address book not copyable: {}", e),
+        }
+    }
+}
+
+impl error::Error for ThunderbirdError {}
+
+impl From<rusqlite::Error> for ThunderbirdError {
+    fn from(e: rusqlite::Error) -> Self {
+        Self::SqliteError(e)
+    }
+}
+
+impl From<io::Error> for ThunderbirdError {
+    fn from(e: io::Error) -> Self {
+        Self::IoError(e)
+    }
+}
+
+/// Reads the cards of a Thunderbird address book (`abook.sqlite`), skipping
+/// broken cards.
+///
+/// The database is opened read-only. If a running Thunderbird has locked
+/// it, a copy in the temporary directory is read instead.
+pub fn read_address_book(path: &Path) -> Result<ParsedVCards, ThunderbirdError> {
+    let cards = match read_properties(path) {
+        Err(ThunderbirdError::SqliteError(e)) if is_locked(&e) => {
+            let copy = TemporaryCopy::new(path)?;
+            read_properties(&copy.path)?
+        }
+        result => result?,
+    };
+    Ok(cards
+        .iter()
+        .flat_map(|(uid, card)| read_card(uid, card))
+        .collect())
+}
+
+fn is_locked(e: &rusqlite::Error) -> bool {
+    matches!(
+        e.sqlite_error_code(),
+        Some(ErrorCode::DatabaseBusy | ErrorCode::DatabaseLocked)
+    )
+}
+
+/// The properties of each card, in the order the cards were added.
+fn read_properties(path: &Path) -> Result<Vec<(String, Card)>, ThunderbirdError> {
+    let connection = Connection::open_with_flags(
+        path,
+        OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+    )?;
+    connection.busy_timeout(BUSY_TIMEOUT)?;
+    let mut statement =
+        connection.prepare("SELECT card, name, value FROM properties ORDER BY rowid")?;
+    let rows = statement.query_map([], |row| {
+        Ok((
+            row.get::<_, String>(0)?,
+            row.get::<_, String>(1)?,
+            row.get::<_, Option<String>>(2)?,
+        ))
+    })?;
+
+    let mut cards: Vec<(String, Card)> = Vec::new();
+    let mut indices = HashMap::new();
+    for row in rows {
+        let (uid, name, value) = row?;
+        let index = *indices.entry(uid.clone()).or_insert_with(|| {
+            cards.push((uid, Card::default()));
+            cards.len() - 1
+        });
+        cards[index].1 .0.insert(name, value.unwrap_or_default());
+    }
+    Ok(cards)
+}
+
+#[derive(Debug, Default)]
+struct Card(HashMap<String, String>);
+
+impl Card {
+    fn value(&self, name: &str) -> Option<&str> {
+        self.0
+            .get(name)
+            .map(|value| value.trim())
+            .filter(|value| !value.is_empty())
+    }
+}
+
+/// Reads the card from its vCard if Thunderbird stored one, otherwise from
+/// the separate fields older versions used.
+fn read_card(uid: &str, card: &Card) -> Vec<Result<VCard, VCardError>> {
+    if let Some(contents) = card.value(VCARD_PROPERTY) {
+        return VCardReader::new(contents.as_bytes())
+            .map(|result| result.map_err(|e| e.in_card(Some(String::from(uid)))))
+            .collect();
+    }
+
+    let properties = contact_properties(
+        card.value("DisplayName"),
+        card.value("FirstName"),
+        card.value("LastName"),
+        card.value("Company"),
+        card.value("NickName"),
+        thunderbird_birthday(
+            card.value("BirthYear"),
+            card.value("BirthMonth"),
+            card.value("BirthDay"),
+        ),
+    );
+    vec![VCard::from_properties(&properties).map_err(|e| e.in_card(Some(String::from(uid))))]
+}
+
+/// Copy of a locked database, together with its write-ahead log, in a
+/// private temporary directory which is removed again when dropped.
+struct TemporaryCopy {
+    path: PathBuf,
+    // SQLite may create other files next to the copy, they go with it
+    _dir: TempDir,
+}
+
+impl TemporaryCopy {
+    fn new(path: &Path) -> io::Result<Self> {
+        let dir = tempfile::Builder::new()
+            .prefix("remember-bday-")
+            .tempdir()?;
+        let copy = dir.path().join("abook.sqlite");
+        fs::copy(path, &copy)?;
+        let wal = wal_path(path);
+        if wal.exists() {
+            fs::copy(wal, wal_path(&copy))?;
+        }
+        Ok(TemporaryCopy {
+            path: copy,
+            _dir: dir,
+        })
+    }
+}
+
+fn wal_path(path: &Path) -> PathBuf {
+    let mut wal = path.as_os_str().to_os_string();
+    wal.push("-wal");
+    PathBuf::from(wal)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vcard::{DateAndOrTime, StructuredName};
+    use chrono::NaiveDate;
+
+    fn create_address_book(path: &Path, properties: &[(&str, &str, &str)]) -> Connection {
+        let connection = Connection::open(path).unwrap();
+        connection
+            .execute_batch(
+                "CREATE TABLE cards (uid TEXT PRIMARY KEY, localId INTEGER);
+                CREATE TABLE properties (card TEXT, name TEXT, value TEXT);",
+            )
+            .unwrap();
+        for (card, name, value) in properties {
+            connection
+                .execute(
+                    "INSERT INTO properties (card, name, value) VALUES (?1, ?2, ?3)",
+                    [card, name, value],
+                )
+                .unwrap();
+        }
+        connection
+    }
+
+    #[test]
+    fn read_cards() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("abook.sqlite");
+        create_address_book(
+            &path,
+            &[
+                ("a", "DisplayName", "Allice Test"),
+                ("a", "FirstName", "Allice"),
+                ("a", "LastName", "Test"),
+                ("a", "BirthYear", "1980"),
+                ("a", "BirthMonth", "5"),
+                ("a", "BirthDay", "7"),
+                (
+                    "b",
+                    "_vCard",
+                    "BEGIN:VCARD\r\nVERSION:4.0\r\nFN:Bob Test\r\nBDAY:--0608\r\nEND:VCARD\r\n",
+                ),
+                ("b", "DisplayName", "Bob Test"),
+                ("c", "DisplayName", "Carl Test"),
+                ("c", "BirthMonth", "13"),
+                ("c", "BirthDay", "1"),
+            ],
+        );
+
+        let parsed = read_address_book(&path).unwrap();
+
+        assert_eq!(
+            vec![
+                VCard {
+                    name: String::from("Allice Test"),
+                    structured_name: Some(StructuredName {
+                        family: vec![String::from("Test")],
+                        given: vec![String::from("Allice")],
+                        ..Default::default()
+                    }),
+                    bday: NaiveDate::from_ymd_opt(1980, 5, 7).map(DateAndOrTime::Date),
+                    ..Default::default()
+                },
+                VCard {
+                    name: String::from("Bob Test"),
+                    bday: Some(DateAndOrTime::Partial {
+                        year: None,
+                        month: Some(6),
+                        day: Some(8)
+                    }),
+                    ..Default::default()
+                },
+            ],
+            parsed.vcards
+        );
+        assert_eq!(1, parsed.diagnostics.len());
+        assert_eq!(Some(String::from("c")), parsed.diagnostics[0].card);
+    }
+
+    #[test]
+    fn read_locked_address_book() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("abook.sqlite");
+        let connection = create_address_book(&path, &[("a", "DisplayName", "Allice Test")]);
+        // like a running Thunderbird
+        connection
+            .execute_batch("PRAGMA locking_mode = EXCLUSIVE; BEGIN EXCLUSIVE;")
+            .unwrap();
+
+        let parsed = read_address_book(&path).unwrap();
+
+        assert_eq!(
+            vec!["Allice Test"],
+            parsed
+                .vcards
+                .iter()
+                .map(|vcard| vcard.name.as_str())
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn report_invalid_address_book() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("abook.sqlite");
+        fs::write(&path, "not a database").unwrap();
+
+        assert!(matches!(
+            read_address_book(&path),
+            Err(ThunderbirdError::SqliteError(_))
+        ));
+    }
+}
@@ -1,150 +1,514 @@
-use chrono::prelude::NaiveDate;
-use std::{error, fmt};
+mod csv;
+mod date;
+mod encoding;
+mod error;
+mod jcard;
+mod ldif;
+mod property;
+mod reader;
+mod writer;
+mod xcard;
 
-#[derive(Debug, PartialEq, Eq)]
+pub use self::csv::{parse_csv, ColumnMapping};
+pub use date::DateAndOrTime;
+pub(crate) use encoding::escape_text;
+pub use error::{ErrorKind, VCardError};
+pub use jcard::{parse_jcards, parse_jcards_lenient};
+pub use ldif::parse_ldif;
+pub use property::{Param, Property};
+pub(crate) use reader::Unfold;
+pub use reader::VCardReader;
+use std::collections::HashMap;
+pub(crate) use writer::fold;
+pub use writer::{write_vcards, VCardWriter, Version};
+pub use xcard::{parse_xcards, parse_xcards_lenient};
+
+#[derive(Debug, Default, PartialEq, Eq)]
 pub struct VCard {
+    /// The display name: `FN`, or derived from `N`, `ORG` or `NICKNAME` if
+    /// `FN` is missing.
     pub name: String,
-    pub bday: Option<NaiveDate>,
+    pub structured_name: Option<StructuredName>,
+    /// Identifies the contact across address books and versions of the card.
+    pub uid: Option<String>,
+    pub bday: Option<DateAndOrTime>,
+    /// Remindable dates besides the birthday.
+    pub events: Vec<Event>,
 }
 
-#[derive(Debug, PartialEq)]
-pub enum VCardError {
-    UnexpectedFieldError(String),
-    MissingEndError,
-    NoNameError,
-    InvalidNameError,
-    InvalidBDayError(String),
-    InvalidMultilineQuotedPrintable,
+/// The components of `N`, each of them can have multiple values (e.g. two
+/// given names).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StructuredName {
+    pub family: Vec<String>,
+    pub given: Vec<String>,
+    pub additional: Vec<String>,
+    pub prefixes: Vec<String>,
+    pub suffixes: Vec<String>,
 }
 
-impl fmt::Display for VCardError {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
-        match self {
-            Self::UnexpectedFieldError(field) => write!(f, "Unexpected {}", field),
-            Self::MissingEndError => write!(f, "Missing END:VCARD"),
-            Self::NoNameError => write!(f, "No name at end of vcard"),
-            Self::InvalidNameError => write!(f, "error while parsing name"),
-            Self::InvalidBDayError(msg) => write!(f, "error while parsing bday: {}", msg),
-            Self::InvalidMultilineQuotedPrintable => write!(f, "error parsing multiline quoted printable")
+impl StructuredName {
+    fn from_components(mut components: Vec<Vec<String>>) -> Self {
+        // missing trailing components are treated as empty, as written by
+        // some vCard 2.1 exporters
+        components.resize(5, Vec::new());
+        let mut components = components.into_iter().map(|values| {
+            values
+                .into_iter()
+                .filter(|value| !value.is_empty())
+                .collect()
+        });
+        let mut next = || components.next().unwrap_or_default();
+        StructuredName {
+            family: next(),
+            given: next(),
+            additional: next(),
+            prefixes: next(),
+            suffixes: next(),
+        }
+    }
+
+    /// The name in western order, like "Dr. Bob Alexander Test Jr.", `None`
+    /// if all components are empty.
+    pub fn display_name(&self) -> Option<String> {
+        let parts: Vec<&str> = [
+            &self.prefixes,
+            &self.given,
+            &self.additional,
+            &self.family,
+            &self.suffixes,
+        ]
+        .into_iter()
+        .flatten()
+        .map(String::as_str)
+        .collect();
+        if parts.is_empty() {
+            None
+        } else {
+            Some(parts.join(" "))
         }
     }
 }
 
-impl error::Error for VCardError {}
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Event {
+    pub kind: EventKind,
+    pub date: DateAndOrTime,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EventKind {
+    /// Wedding anniversary, from `ANNIVERSARY`, `X-ANNIVERSARY`,
+    /// `X-EVOLUTION-ANNIVERSARY` or an Apple date labeled as anniversary.
+    Anniversary,
+    /// An Apple date (`X-ABDATE`) labeled as "other" or without a label.
+    Other,
+    /// An Apple date with a custom label, e.g. "name day".
+    Custom(String),
+}
+
+/// vCards parsed in lenient mode, together with the errors of the skipped
+/// cards.
+#[derive(Debug, Default, PartialEq)]
+pub struct ParsedVCards {
+    pub vcards: Vec<VCard>,
+    pub diagnostics: Vec<VCardError>,
+}
+
+impl FromIterator<Result<VCard, VCardError>> for ParsedVCards {
+    fn from_iter<I: IntoIterator<Item = Result<VCard, VCardError>>>(results: I) -> Self {
+        let mut parsed = ParsedVCards::default();
+        for result in results {
+            match result {
+                Ok(vcard) => parsed.vcards.push(vcard),
+                Err(diagnostic) => parsed.diagnostics.push(diagnostic),
+            }
+        }
+        parsed
+    }
+}
+
+/// Parses all vCards in `contents`, failing at the first error.
+pub fn parse_vcards(contents: impl AsRef<[u8]>) -> Result<Vec<VCard>, VCardError> {
+    VCardReader::new(contents.as_ref()).collect()
+}
+
+/// Parses all vCards in `contents`, skipping broken cards (and anything else
+/// that isn't part of a card) instead of failing.
+pub fn parse_vcards_lenient(contents: impl AsRef<[u8]>) -> ParsedVCards {
+    VCardReader::new(contents.as_ref()).collect()
+}
 
-#[derive(PartialEq)]
 enum ParseState {
     In,
     Out,
-    MultilineQuotedPrintable,
+    /// A quoted-printable value ending with a soft line break (`=`), which
+    /// continues on the next line (RFC 2045 section 6.7).
+    MultilineQuotedPrintable {
+        line_number: usize,
+        line: String,
+        property: Property,
+    },
+    /// After an error, the rest of the broken card (or content outside of a
+    /// card) is skipped until the next `BEGIN:VCARD` or `END:VCARD`.
+    Skip,
+}
+
+struct Parser {
+    state: ParseState,
+    builder: VCardBuilder,
+    /// Line of the current card's `BEGIN:VCARD`.
+    begin_line: Option<(usize, String)>,
 }
 
-pub fn parse_vcards(contents: String) -> Result<Vec<VCard>, VCardError> {
-    let mut result: Vec<VCard> = Vec::new();
-    let mut parse_state = ParseState::Out;
-
-    let mut name: Option<String> = None;
-    let mut bday: Option<NaiveDate> = None;
-    let mut partial_name_line: Option<String> = None;
-
-    for line in contents.lines() {
-        match line {
-            "BEGIN:VCARD" => {
-                if parse_state != ParseState::Out {
-                    return Err(VCardError::UnexpectedFieldError(String::from(
-                        "BEGIN:VCARD",
-                    )));
+impl Parser {
+    fn new() -> Self {
+        Parser {
+            state: ParseState::Out,
+            builder: VCardBuilder::default(),
+            begin_line: None,
+        }
+    }
+
+    /// Processes an unfolded line, returning the vCard it completes.
+    fn parse_line(
+        &mut self,
+        line_number: usize,
+        line: Vec<u8>,
+    ) -> Result<Option<VCard>, VCardError> {
+        if let ParseState::Skip = self.state {
+            if line.eq_ignore_ascii_case(b"BEGIN:VCARD") {
+                self.begin(line_number, "BEGIN:VCARD");
+            } else if line.eq_ignore_ascii_case(b"END:VCARD") {
+                self.state = ParseState::Out;
+            }
+            return Ok(None);
+        }
+
+        let line = match String::from_utf8(line) {
+            Ok(line) => line,
+            Err(e) => {
+                let lossy = String::from_utf8_lossy(e.as_bytes()).into_owned();
+                match encoding::decode_legacy_line(e.into_bytes()) {
+                    Some(line) => line,
+                    None => {
+                        let error =
+                            VCardError::from(ErrorKind::InvalidUtf8Error).at(line_number, &lossy);
+                        return Err(self.skip(error));
+                    }
                 }
-                parse_state = ParseState::In;
             }
-            "END:VCARD" => {
-                if parse_state == ParseState::Out {
-                    return Err(VCardError::UnexpectedFieldError(String::from("END:VCARD")));
+        };
+
+        let state = std::mem::replace(&mut self.state, ParseState::In);
+        if let ParseState::MultilineQuotedPrintable {
+            line_number: first_line_number,
+            line: first_line,
+            mut property,
+        } = state
+        {
+            if !line.eq_ignore_ascii_case("END:VCARD") {
+                property.value.push_str(&line);
+                if property.value.ends_with('=') {
+                    property.value.pop();
+                    self.state = ParseState::MultilineQuotedPrintable {
+                        line_number: first_line_number,
+                        line: first_line,
+                        property,
+                    };
+                } else {
+                    self.add_property(first_line_number, &first_line, &property)?;
                 }
-                parse_state = ParseState::Out;
-                let vcard = match name {
-                    Some(name) => VCard { name, bday },
-                    None => return Err(VCardError::NoNameError),
-                };
-                result.push(vcard);
-                name = None;
-                bday = None;
+                return Ok(None);
             }
-            line => {
-                match parse_state {
-                    ParseState::In => {
-                        if line.starts_with("FN:") {
-                            name = Some(String::from(&line[3..]));
-                        } else if line.starts_with("FN;CHARSET=UTF-8;ENCODING=QUOTED-PRINTABLE:") {
-                            if line.ends_with('=') {
-                                let mut new_partial = line.to_string();
-                                new_partial.pop();
-                                partial_name_line = new_partial.into();
-                                parse_state = ParseState::MultilineQuotedPrintable;
-                            } else {
-                                let decoded_name = decode_quoted_printable(&line[43..])?;
-                                name = Some(decoded_name);
-                            }
-                        } else if line.starts_with("BDAY:") {
-                            let res = NaiveDate::parse_from_str(&line[5..], "%Y-%m-%d");
-
-                            bday = match res {
-                                Ok(nd) => Some(nd),
-                                Err(pe) => {
-                                    return Err(VCardError::InvalidBDayError(pe.to_string()))
-                                }
-                            };
-                        }
-                    }
-                    ParseState::Out => {
-                        return Err(VCardError::UnexpectedFieldError(String::from("contents")))
+        } else {
+            self.state = state;
+        }
+
+        // vCard 2.1 ends base64 values with a blank line
+        if line.is_empty() {
+            return Ok(None);
+        }
+
+        let unexpected = |field: &str| {
+            VCardError::from(ErrorKind::UnexpectedFieldError(String::from(field)))
+                .at(line_number, &line)
+        };
+        let property = match Property::parse(&line) {
+            Ok(property) => property,
+            Err(_) if matches!(self.state, ParseState::Out) => {
+                return Err(self.skip(unexpected("contents")))
+            }
+            Err(e) => {
+                let error = e
+                    .at(line_number, &line)
+                    .in_card(self.builder.identification());
+                return Err(self.skip(error));
+            }
+        };
+        let is_vcard = property.value.eq_ignore_ascii_case("VCARD");
+        match (property.name.as_str(), &self.state) {
+            ("BEGIN", ParseState::Out) if is_vcard => self.begin(line_number, &line),
+            ("BEGIN", _) if is_vcard => {
+                // the previous card is dropped, the new one is parsed
+                let error = unexpected("BEGIN:VCARD").in_card(self.builder.identification());
+                self.begin(line_number, &line);
+                return Err(error);
+            }
+            ("END", ParseState::Out) if is_vcard => return Err(unexpected("END:VCARD")),
+            ("END", _) if is_vcard => {
+                self.state = ParseState::Out;
+                let builder = std::mem::take(&mut self.builder);
+                let card = builder.identification();
+                return match builder.build() {
+                    Ok(vcard) => Ok(Some(vcard)),
+                    Err(e) => {
+                        let (begin_line_number, begin_line) =
+                            self.begin_line.take().unwrap_or_default();
+                        Err(e.at(begin_line_number, &begin_line).in_card(card))
                     }
-                    ParseState::MultilineQuotedPrintable => {
-                        if !line.starts_with('=') {
-                            return Err(VCardError::InvalidMultilineQuotedPrintable);
-                        }
-
-                        let mut new_partial = partial_name_line.clone().unwrap();
-                        new_partial.push_str(line);
-                        if line.ends_with('=') {
-                            new_partial.pop();
-                            partial_name_line = new_partial.into();
-                        } else {
-                            let decoded_name = decode_quoted_printable(&new_partial[43..])?;
-                            name = Some(decoded_name);
-                            parse_state = ParseState::In;
-                        }
-                    },
+                };
+            }
+            (_, ParseState::Out) => return Err(self.skip(unexpected("contents"))),
+            _ if property.is_quoted_printable() && property.value.ends_with('=') => {
+                let mut property = property;
+                property.value.pop();
+                self.state = ParseState::MultilineQuotedPrintable {
+                    line_number,
+                    line,
+                    property,
+                };
+            }
+            _ => self.add_property(line_number, &line, &property)?,
+        }
+        Ok(None)
+    }
+
+    fn begin(&mut self, line_number: usize, line: &str) {
+        self.state = ParseState::In;
+        self.builder = VCardBuilder::default();
+        self.begin_line = Some((line_number, String::from(line)));
+    }
+
+    fn add_property(
+        &mut self,
+        line_number: usize,
+        line: &str,
+        property: &Property,
+    ) -> Result<(), VCardError> {
+        self.builder.add(property).map_err(|e| {
+            let error = e
+                .at(line_number, line)
+                .with_column(value_column(line))
+                .in_card(self.builder.identification());
+            self.skip(error)
+        })
+    }
+
+    /// Checks that the last card was ended.
+    fn finish(&mut self) -> Result<(), VCardError> {
+        match self.state {
+            ParseState::Out | ParseState::Skip => Ok(()),
+            _ => {
+                let (line_number, line) = self.begin_line.take().unwrap_or_default();
+                Err(VCardError::from(ErrorKind::MissingEndError)
+                    .at(line_number, &line)
+                    .in_card(self.builder.identification()))
+            }
+        }
+    }
+
+    fn skip(&mut self, error: VCardError) -> VCardError {
+        self.state = ParseState::Skip;
+        error
+    }
+}
+
+/// The column where the value starts in the (first) line of a property,
+/// after the first `:` that isn't part of a quoted parameter value.
+fn value_column(line: &str) -> usize {
+    let mut quoted = false;
+    for (index, c) in line.chars().enumerate() {
+        match c {
+            '"' => quoted = !quoted,
+            ':' if !quoted => return index + 2,
+            _ => (),
+        }
+    }
+    1
+}
+
+impl VCard {
+    /// Builds a vCard from the properties between `BEGIN:VCARD` and
+    /// `END:VCARD`.
+    pub fn from_properties(properties: &[Property]) -> Result<VCard, VCardError> {
+        let mut builder = VCardBuilder::default();
+        for property in properties {
+            builder.add(property)?;
+        }
+        builder.build()
+    }
+}
+
+/// Collects the properties of a single card.
+#[derive(Default)]
+struct VCardBuilder {
+    name: Option<String>,
+    structured_name: Option<StructuredName>,
+    org: Option<String>,
+    nickname: Option<String>,
+    bday: Option<DateAndOrTime>,
+    events: Vec<Event>,
+    // Apple dates and their labels are grouped like `item1.X-ABDATE` and
+    // `item1.X-ABLabel`, in any order. The dates are added to `events` right
+    // away to keep their order, their kind is set once all labels are known.
+    apple_dates: Vec<(usize, Option<String>)>,
+    apple_labels: HashMap<String, String>,
+    uid: Option<String>,
+}
+
+impl VCardBuilder {
+    fn add(&mut self, property: &Property) -> Result<(), VCardError> {
+        match property.name.as_str() {
+            "FN" => self.name = Some(property.text_value()?),
+            "N" => {
+                // an empty `N` (required in vCard 3.0) carries no name
+                self.structured_name = Some(StructuredName::from_components(
+                    property.structured_value()?,
+                ))
+                .filter(|name| name != &StructuredName::default());
+            }
+            "ORG" => {
+                // only the organization name, without the units
+                let components = property.structured_value()?;
+                self.org = components.first().map(|name| name.join(","));
+            }
+            "NICKNAME" => {
+                let components = property.structured_value()?;
+                self.nickname = components.into_iter().flatten().next();
+            }
+            "UID" => self.uid = Some(property.text_value()?),
+            "BDAY" => {
+                self.bday = Some(parse_date(property).map_err(ErrorKind::InvalidBDayError)?);
+            }
+            "ANNIVERSARY" | "X-ANNIVERSARY" | "X-EVOLUTION-ANNIVERSARY" => {
+                self.events.push(Event {
+                    kind: EventKind::Anniversary,
+                    date: parse_date(property).map_err(ErrorKind::InvalidDateError)?,
+                });
+            }
+            "X-ABDATE" => {
+                let date = parse_date(property).map_err(ErrorKind::InvalidDateError)?;
+                self.apple_dates
+                    .push((self.events.len(), property.group.clone()));
+                self.events.push(Event {
+                    kind: EventKind::Other,
+                    date,
+                });
+            }
+            "X-ABLABEL" => {
+                if let Some(group) = &property.group {
+                    self.apple_labels
+                        .insert(group.to_ascii_uppercase(), property.text_value()?);
                 }
             }
+            _ => (),
+        }
+        Ok(())
+    }
+
+    fn build(mut self) -> Result<VCard, VCardError> {
+        for (index, group) in std::mem::take(&mut self.apple_dates) {
+            let label = group.and_then(|group| self.apple_labels.get(&group.to_ascii_uppercase()));
+            self.events[index].kind = apple_event_kind(label.map(String::as_str));
+        }
+        // Evolution and Apple exports often repeat a date in several properties
+        let mut events: Vec<Event> = Vec::with_capacity(self.events.len());
+        for event in std::mem::take(&mut self.events) {
+            if !events.contains(&event) {
+                events.push(event);
+            }
+        }
+
+        match self.display_name() {
+            Some(name) => Ok(VCard {
+                name,
+                structured_name: self.structured_name,
+                uid: self.uid,
+                bday: self.bday,
+                events,
+            }),
+            None => Err(ErrorKind::NoNameError.into()),
         }
     }
 
-    match parse_state {
-        ParseState::Out => Ok(result),
-        _ => Err(VCardError::MissingEndError),
+    /// The first of `FN`, `N`, `ORG` and `NICKNAME` that isn't blank.
+    fn display_name(&self) -> Option<String> {
+        let not_blank = |name: &String| !name.trim().is_empty();
+        self.name
+            .clone()
+            .filter(not_blank)
+            .or_else(|| {
+                self.structured_name
+                    .as_ref()
+                    .and_then(StructuredName::display_name)
+            })
+            .or_else(|| self.org.clone().filter(not_blank))
+            .or_else(|| self.nickname.clone().filter(not_blank))
+    }
+
+    /// Display name or `UID` of the card, as far as known.
+    fn identification(&self) -> Option<String> {
+        self.display_name().or_else(|| self.uid.clone())
     }
 }
 
-fn decode_quoted_printable(encoded: &str) -> Result<String, VCardError> {
-    let mut bytes = Vec::new();
-    for s in encoded.split("=").skip(1) {
-        let b = match u8::from_str_radix(s, 16) {
-            Ok(b) => b,
-            Err(_) => return Err(VCardError::InvalidNameError),
-        };
-        bytes.push(b);
+/// Parses a date property like `BDAY` or `ANNIVERSARY`, honoring the `VALUE`
+/// parameter and Apple's `X-APPLE-OMIT-YEAR` placeholder year for dates
+/// without a year.
+fn parse_date(property: &Property) -> Result<DateAndOrTime, String> {
+    let value = property.text_value().map_err(|e| e.kind.to_string())?;
+    if property.has_param_value("VALUE", "text") {
+        return Ok(DateAndOrTime::Text(value));
     }
 
-    match String::from_utf8(bytes) {
-        Ok(s) => Ok(s),
-        Err(_) => Err(VCardError::InvalidNameError),
+    let date = DateAndOrTime::parse(&value)?;
+    let omit_year = property
+        .param_value("X-APPLE-OMIT-YEAR")
+        .and_then(|year| year.parse::<i32>().ok());
+    match omit_year {
+        Some(year) if date.year() == Some(year) => Ok(date.without_year()),
+        _ => Ok(date),
+    }
+}
+
+/// Maps the label of an Apple date to an event kind. Apple's predefined
+/// labels look like `_$!<Anniversary>!$_`.
+fn apple_event_kind(label: Option<&str>) -> EventKind {
+    match label {
+        Some("_$!<Anniversary>!$_") => EventKind::Anniversary,
+        None | Some("_$!<Other>!$_") => EventKind::Other,
+        Some(label) => EventKind::Custom(label.to_string()),
     }
 }
 
 #[cfg(test)]
+#[allow(clippy::unnecessary_to_owned)]
 mod tests {
     use super::*;
+    use chrono::NaiveDate;
+
+    fn structured_name(family: &str, given: &str) -> Option<StructuredName> {
+        Some(StructuredName {
+            family: vec![String::from(family)],
+            given: [given]
+                .into_iter()
+                .filter(|given| !given.is_empty())
+                .map(String::from)
+                .collect(),
+            ..Default::default()
+        })
+    }
 
     #[test]
     fn parse_vcards_ok() {
@@ -181,19 +545,27 @@ END:VCARD
         let expected = vec![
             VCard {
                 name: String::from("Allice Test"),
+                structured_name: structured_name("Test", "Allice"),
                 bday: None,
+                ..Default::default()
             },
             VCard {
                 name: String::from("Bob Test"),
-                bday: NaiveDate::from_ymd_opt(1980, 5, 7),
+                structured_name: structured_name("Test", "Bob"),
+                bday: NaiveDate::from_ymd_opt(1980, 5, 7).map(DateAndOrTime::Date),
+                ..Default::default()
             },
             VCard {
                 name: String::from("Täst"),
+                structured_name: structured_name("Täst", ""),
                 bday: None,
+                ..Default::default()
             },
             VCard {
                 name: String::from("Multiline Täst"),
+                structured_name: structured_name("Täst", "Multiline"),
                 bday: None,
+                ..Default::default()
             },
         ];
         assert_eq!(expected, result);
@@ -217,8 +589,8 @@ END:VCARD";
         let result = parse_vcards(input.to_string()).unwrap_err();
 
         assert_eq!(
-            VCardError::UnexpectedFieldError(String::from("contents")),
-            result
+            ErrorKind::UnexpectedFieldError(String::from("contents")),
+            result.kind
         );
     }
 
@@ -236,8 +608,8 @@ END:VCARD";
         let result = parse_vcards(input.to_string()).unwrap_err();
 
         assert_eq!(
-            VCardError::UnexpectedFieldError(String::from("END:VCARD")),
-            result
+            ErrorKind::UnexpectedFieldError(String::from("END:VCARD")),
+            result.kind
         );
     }
 
@@ -255,8 +627,8 @@ END:VCARD";
         let result = parse_vcards(input.to_string()).unwrap_err();
 
         assert_eq!(
-            VCardError::UnexpectedFieldError(String::from("BEGIN:VCARD")),
-            result
+            ErrorKind::UnexpectedFieldError(String::from("BEGIN:VCARD")),
+            result.kind
         );
     }
 
@@ -271,7 +643,7 @@ BDAY:1980-05-07";
 
         let result = parse_vcards(input.to_string()).unwrap_err();
 
-        assert_eq!(VCardError::MissingEndError, result);
+        assert_eq!(ErrorKind::MissingEndError, result.kind);
     }
 
     #[test]
@@ -284,14 +656,118 @@ FN:Allice Test
 TEL;CELL:+01234567890
 END:VCARD
 BEGIN:VCARD
-VERSION:2.1
-N:Test;Bob;;;
+VERSION:3.0
+N:;;;;
+ORG:;Unit
+NICKNAME:
 BDAY:1980-05-07
 END:VCARD";
 
         let result = parse_vcards(input.to_string()).unwrap_err();
 
-        assert_eq!(VCardError::NoNameError, result);
+        assert_eq!(ErrorKind::NoNameError, result.kind);
+    }
+
+    #[test]
+    fn parse_vcards_name_fallback() {
+        let input = "\
+BEGIN:VCARD
+VERSION:2.1
+N:Test;Bob;Alexander,Bobby;Dr.;Jr.
+BDAY:1980-05-07
+END:VCARD
+BEGIN:VCARD
+VERSION:3.0
+FN:
+N:Test;Allice;;;
+END:VCARD
+BEGIN:VCARD
+VERSION:3.0
+FN:  
+ORG:Test Inc.
+END:VCARD
+BEGIN:VCARD
+VERSION:2.1
+N:Test
+END:VCARD
+BEGIN:VCARD
+VERSION:3.0
+N:;;;;
+ORG:Test Inc.;Research
+NICKNAME:Tester
+END:VCARD
+BEGIN:VCARD
+VERSION:4.0
+NICKNAME:Tester,Testy
+END:VCARD";
+
+        let result = parse_vcards(input).unwrap();
+
+        assert_eq!(
+            vec![
+                VCard {
+                    name: String::from("Dr. Bob Alexander Bobby Test Jr."),
+                    structured_name: Some(StructuredName {
+                        family: vec![String::from("Test")],
+                        given: vec![String::from("Bob")],
+                        additional: vec![String::from("Alexander"), String::from("Bobby")],
+                        prefixes: vec![String::from("Dr.")],
+                        suffixes: vec![String::from("Jr.")],
+                    }),
+                    bday: NaiveDate::from_ymd_opt(1980, 5, 7).map(DateAndOrTime::Date),
+                    ..Default::default()
+                },
+                VCard {
+                    name: String::from("Allice Test"),
+                    structured_name: structured_name("Test", "Allice"),
+                    ..Default::default()
+                },
+                VCard {
+                    name: String::from("Test Inc."),
+                    ..Default::default()
+                },
+                VCard {
+                    name: String::from("Test"),
+                    structured_name: structured_name("Test", ""),
+                    ..Default::default()
+                },
+                VCard {
+                    name: String::from("Test Inc."),
+                    ..Default::default()
+                },
+                VCard {
+                    name: String::from("Tester"),
+                    ..Default::default()
+                },
+            ],
+            result
+        );
+    }
+
+    #[test]
+    fn parse_vcards_escaped_values() {
+        let input = r"BEGIN:VCARD
+VERSION:3.0
+FN:Smith\, John\nJr.
+N:Smith\, Jr.;John\;Jo;;;
+item1.X-ABDATE:2010-08-01
+item1.X-ABLabel:first\, and only\, date
+END:VCARD";
+
+        let result = parse_vcards(input).unwrap();
+
+        assert_eq!(
+            vec![VCard {
+                name: String::from("Smith, John\nJr."),
+                structured_name: structured_name("Smith, Jr.", "John;Jo"),
+                events: vec![Event {
+                    kind: EventKind::Custom(String::from("first, and only, date")),
+                    date: DateAndOrTime::Date(NaiveDate::from_ymd_opt(2010, 8, 1).unwrap()),
+                }],
+                ..Default::default()
+            }],
+            result
+        );
     }
 
     #[test]
@@ -313,25 +789,30 @@ END:VCARD";
         let result = parse_vcards(input.to_string()).unwrap_err();
 
         assert_eq!(
-            VCardError::InvalidBDayError(String::from("input contains invalid characters")),
-            result
+            ErrorKind::InvalidBDayError(String::from("input contains invalid characters")),
+            result.kind
         );
     }
 
     #[test]
-    fn parse_vcards_multiline_unexpected_content() {
+    fn parse_vcards_multiline_literal_continuation() {
+        // soft line breaks may be followed by literal characters
         let input = "\
 BEGIN:VCARD
 VERSION:2.1
 N:Täst;Multiline;;;
 FN;CHARSET=UTF-8;ENCODING=QUOTED-PRINTABLE:=4D=75=6C=74=69=
-OTHER_FIELD:WRONG
+line T=C3=A4st
 END:VCARD";
 
-        let result = parse_vcards(input.to_string()).unwrap_err();
+        let result = parse_vcards(input).unwrap();
 
         assert_eq!(
-            VCardError::InvalidMultilineQuotedPrintable,
+            vec![VCard {
+                name: String::from("Multiline Täst"),
+                structured_name: structured_name("Täst", "Multiline"),
+                ..Default::default()
+            }],
             result
         );
     }
@@ -341,15 +822,589 @@ END:VCARD";
         let input = "\
 BEGIN:VCARD
 VERSION:2.1
-N:Täst;Multiline;;;
 FN;CHARSET=UTF-8;ENCODING=QUOTED-PRINTABLE:=4D=75=6C=74=69=
 END:VCARD";
 
         let result = parse_vcards(input.to_string()).unwrap_err();
 
         assert_eq!(
-            VCardError::NoNameError,
+            ErrorKind::NoNameError,
+            result.kind
+        );
+    }
+
+    #[test]
+    fn parse_vcards_folded_lines() {
+        let input = "\
+BEGIN:VCARD\r
+VERSION:4.0\r
+N:Test;Bob;;;\r
+FN:Bob with a very long name that an exporter folded over more than one \r
+ line\r
+BDAY:1980-\r
+ 05-07\r
+END:VCARD\r
+BEGIN:VCARD\r
+VERSION:3.0\r
+N:Test;Tab\r
+\t;;;\r
+F\r
+ N:Tab Test\r
+END:VCARD\r
+";
+
+        let result = parse_vcards(input).unwrap();
+        let expected = vec![
+            VCard {
+                name: String::from(
+                    "Bob with a very long name that an exporter folded over more than one line",
+                ),
+                structured_name: structured_name("Test", "Bob"),
+                bday: NaiveDate::from_ymd_opt(1980, 5, 7).map(DateAndOrTime::Date),
+                ..Default::default()
+            },
+            VCard {
+                name: String::from("Tab Test"),
+                structured_name: structured_name("Test", "Tab"),
+                bday: None,
+                ..Default::default()
+            },
+        ];
+        assert_eq!(expected, result);
+    }
+
+    #[test]
+    fn parse_vcards_folded_inside_utf8_sequence() {
+        // "ä" is 0xC3 0xA4, folded right between the two bytes
+        let mut input = b"BEGIN:VCARD\r\nVERSION:4.0\r\nFN:T\xC3\r\n \xA4st\r\n".to_vec();
+        // "😀" is 0xF0 0x9F 0x98 0x80, folded twice
+        input.extend_from_slice(b"NOTE:\xF0\x9F\r\n \x98\r\n \x80\r\nEND:VCARD\r\n");
+
+        let result = parse_vcards(input).unwrap();
+        let expected = vec![VCard {
+            name: String::from("Täst"),
+            bday: None,
+            ..Default::default()
+        }];
+        assert_eq!(expected, result);
+    }
+
+    #[test]
+    fn parse_vcards_invalid_utf8() {
+        let input = b"BEGIN:VCARD\nVERSION:4.0\nFN:T\xC3st\nEND:VCARD\n";
+
+        let result = parse_vcards(input).unwrap_err();
+
+        assert_eq!(ErrorKind::InvalidUtf8Error, result.kind);
+    }
+
+    #[test]
+    fn parse_vcards_bday_forms() {
+        let input = "\
+BEGIN:VCARD
+VERSION:3.0
+FN:Basic
+BDAY:19800507
+END:VCARD
+BEGIN:VCARD
+VERSION:4.0
+FN:Yearless
+BDAY:--0507
+END:VCARD
+BEGIN:VCARD
+VERSION:4.0
+FN:Value Date
+BDAY;VALUE=date:1980-05-07
+END:VCARD
+BEGIN:VCARD
+VERSION:3.0
+FN:Date Time
+BDAY:1980-05-07T00:00:00Z
+END:VCARD
+BEGIN:VCARD
+VERSION:4.0
+FN:Text
+BDAY;VALUE=text:circa 1800
+END:VCARD
+BEGIN:VCARD
+VERSION:3.0
+FN:Apple Yearless
+BDAY;X-APPLE-OMIT-YEAR=1604:1604-05-07
+END:VCARD
+";
+
+        let date = NaiveDate::from_ymd_opt(1980, 5, 7).unwrap();
+        let yearless = DateAndOrTime::Partial {
+            year: None,
+            month: Some(5),
+            day: Some(7),
+        };
+        let result = parse_vcards(input).unwrap();
+        let expected = vec![
+            VCard {
+                name: String::from("Basic"),
+                bday: Some(DateAndOrTime::Date(date)),
+                ..Default::default()
+            },
+            VCard {
+                name: String::from("Yearless"),
+                bday: Some(yearless.clone()),
+                ..Default::default()
+            },
+            VCard {
+                name: String::from("Value Date"),
+                bday: Some(DateAndOrTime::Date(date)),
+                ..Default::default()
+            },
+            VCard {
+                name: String::from("Date Time"),
+                bday: Some(DateAndOrTime::DateTime(date.and_hms_opt(0, 0, 0).unwrap())),
+                ..Default::default()
+            },
+            VCard {
+                name: String::from("Text"),
+                bday: Some(DateAndOrTime::Text(String::from("circa 1800"))),
+                ..Default::default()
+            },
+            VCard {
+                name: String::from("Apple Yearless"),
+                bday: Some(yearless),
+                ..Default::default()
+            },
+        ];
+        assert_eq!(expected, result);
+    }
+
+    #[test]
+    fn parse_vcards_events() {
+        let input = "\
+BEGIN:VCARD
+VERSION:4.0
+FN:Alice & Bob
+ANNIVERSARY:20040621
+END:VCARD
+BEGIN:VCARD
+VERSION:3.0
+FN:Evolution
+X-EVOLUTION-ANNIVERSARY:2004-06-21
+X-ANNIVERSARY:--0621
+END:VCARD
+BEGIN:VCARD
+VERSION:3.0
+FN:Apple
+item1.X-ABDATE;type=pref:2004-06-21
+item1.X-ABLabel:_$!<Anniversary>!$_
+item2.X-ABLabel:name day
+item2.X-ABDATE:1604-12-06
+item3.X-ABDATE:2010-01-01
+END:VCARD
+";
+
+        let date = DateAndOrTime::Date(NaiveDate::from_ymd_opt(2004, 6, 21).unwrap());
+        let anniversary = |date: &DateAndOrTime| Event {
+            kind: EventKind::Anniversary,
+            date: date.clone(),
+        };
+        let result = parse_vcards(input).unwrap();
+        let expected = vec![
+            VCard {
+                name: String::from("Alice & Bob"),
+                events: vec![anniversary(&date)],
+                ..Default::default()
+            },
+            VCard {
+                name: String::from("Evolution"),
+                events: vec![
+                    anniversary(&date),
+                    anniversary(&date.clone().without_year()),
+                ],
+                ..Default::default()
+            },
+            VCard {
+                name: String::from("Apple"),
+                events: vec![
+                    anniversary(&date),
+                    Event {
+                        kind: EventKind::Custom(String::from("name day")),
+                        date: DateAndOrTime::Date(NaiveDate::from_ymd_opt(1604, 12, 6).unwrap()),
+                    },
+                    Event {
+                        kind: EventKind::Other,
+                        date: DateAndOrTime::Date(NaiveDate::from_ymd_opt(2010, 1, 1).unwrap()),
+                    },
+                ],
+                ..Default::default()
+            },
+        ];
+        assert_eq!(expected, result);
+    }
+
+    #[test]
+    fn parse_vcards_duplicate_events() {
+        let input = "\
+BEGIN:VCARD
+VERSION:3.0
+FN:Evolution
+X-EVOLUTION-ANNIVERSARY:2004-06-21
+X-ANNIVERSARY:2004-06-21
+item1.X-ABDATE:2004-06-21
+item1.X-ABLabel:_$!<Anniversary>!$_
+END:VCARD
+";
+
+        let result = parse_vcards(input).unwrap();
+
+        assert_eq!(
+            vec![Event {
+                kind: EventKind::Anniversary,
+                date: DateAndOrTime::Date(NaiveDate::from_ymd_opt(2004, 6, 21).unwrap()),
+            }],
+            result[0].events
+        );
+    }
+
+    #[test]
+    fn parse_vcards_invalid_anniversary() {
+        let input = "\
+BEGIN:VCARD
+VERSION:4.0
+FN:Alice & Bob
+ANNIVERSARY:2004-13-21
+END:VCARD";
+
+        let result = parse_vcards(input).unwrap_err();
+
+        assert_eq!(
+            ErrorKind::InvalidDateError(String::from("input is out of range")),
+            result.kind
+        );
+    }
+
+    #[test]
+    fn parse_vcards_any_parameter_order_and_case() {
+        let input = "\
+begin:vcard
+version:2.1
+fn;encoding=quoted-printable;charset=utf-8:=54=C3=A4=73=74
+bday;value=DATE:1980-05-07
+end:vcard
+BEGIN:VCARD
+VERSION:2.1
+FN;ENCODING=QUOTED-PRINTABLE;CHARSET=UTF-8:=4D=75=6C=74=69=
+=6C=69=6E=65=20=
+=54=C3=A4=73=74
+END:VCARD
+BEGIN:VCARD
+VERSION:3.0
+item1.FN;X-PARAM=\"quoted;with:separators\":Grouped Test
+END:VCARD
+";
+
+        let result = parse_vcards(input).unwrap();
+        let expected = vec![
+            VCard {
+                name: String::from("Täst"),
+                bday: NaiveDate::from_ymd_opt(1980, 5, 7).map(DateAndOrTime::Date),
+                ..Default::default()
+            },
+            VCard {
+                name: String::from("Multiline Täst"),
+                ..Default::default()
+            },
+            VCard {
+                name: String::from("Grouped Test"),
+                ..Default::default()
+            },
+        ];
+        assert_eq!(expected, result);
+    }
+
+    #[test]
+    fn parse_vcards_invalid_content_line() {
+        let input = "\
+BEGIN:VCARD
+VERSION:4.0
+FN:Bob Test
+this is not a content line
+END:VCARD";
+
+        let result = parse_vcards(input).unwrap_err();
+
+        assert_eq!(
+            ErrorKind::InvalidPropertyError(String::from(
+                "missing ':': this is not a content line"
+            )),
+            result.kind
+        );
+    }
+
+    #[test]
+    fn parse_vcards_legacy_encodings() {
+        let mut input = b"\
+BEGIN:VCARD\r
+VERSION:2.1\r
+N;CHARSET=ISO-8859-1;ENCODING=QUOTED-PRINTABLE:M=FCller;J=F6rg;;;\r
+FN;CHARSET=ISO-8859-1;ENCODING=QUOTED-PRINTABLE:J=F6rg M=FC=\r
+ller\r
+BDAY;ENCODING=QUOTED-PRINTABLE:1980-05-=\r
+07\r
+END:VCARD\r
+BEGIN:VCARD\r
+VERSION:2.1\r
+"
+        .to_vec();
+        input.extend_from_slice(b"FN;CHARSET=WINDOWS-1252:\x80 G\xFCnther\r\n");
+        input.extend_from_slice(
+            b"PHOTO;ENCODING=BASE64;TYPE=JPEG:\r\n /9j/4AAQ\r\n SkZJRg==\r\n\r\n",
+        );
+        input.extend_from_slice(b"END:VCARD\r\n");
+        input.extend_from_slice(b"BEGIN:VCARD\r\nVERSION:3.0\r\n");
+        input.extend_from_slice(b"FN;ENCODING=b;CHARSET=UTF-8:QmFzZSBUw6RzdA==\r\nEND:VCARD\r\n");
+
+        let result = parse_vcards(input).unwrap();
+        let expected = vec![
+            VCard {
+                name: String::from("Jörg Müller"),
+                structured_name: structured_name("Müller", "Jörg"),
+                bday: NaiveDate::from_ymd_opt(1980, 5, 7).map(DateAndOrTime::Date),
+                ..Default::default()
+            },
+            VCard {
+                name: String::from("€ Günther"),
+                ..Default::default()
+            },
+            VCard {
+                name: String::from("Base Täst"),
+                ..Default::default()
+            },
+        ];
+        assert_eq!(expected, result);
+    }
+
+    #[test]
+    fn parse_vcards_invalid_encoding() {
+        let input = "\
+BEGIN:VCARD
+VERSION:2.1
+FN;CHARSET=UTF-8;ENCODING=QUOTED-PRINTABLE:T=E4st
+END:VCARD";
+
+        let result = parse_vcards(input).unwrap_err();
+
+        assert_eq!(
+            ErrorKind::InvalidEncodingError(String::from("invalid UTF-8")),
+            result.kind
+        );
+    }
+
+    #[test]
+    fn parse_vcards_lenient_skips_broken_cards() {
+        let input = "\
+VERSION:2.1
+FN:Stray Test
+END:VCARD
+BEGIN:VCARD
+VERSION:2.1
+FN:Allice Test
+END:VCARD
+BEGIN:VCARD
+VERSION:2.1
+FN:Bob Test
+BDAY:1980-asdf-07
+END:VCARD
+END:VCARD
+BEGIN:VCARD
+VERSION:2.1
+N:Test;Charlie;;;
+BEGIN:VCARD
+VERSION:2.1
+FN:Dave Test
+this is not a content line
+TEL:+01234567890
+END:VCARD
+BEGIN:VCARD
+FN:Eve Test
+END:VCARD
+BEGIN:VCARD
+N:Test;Mallory;;;
+";
+
+        let result = parse_vcards_lenient(input);
+
+        assert_eq!(
+            vec![
+                VCard {
+                    name: String::from("Allice Test"),
+                    ..Default::default()
+                },
+                VCard {
+                    name: String::from("Eve Test"),
+                    ..Default::default()
+                },
+            ],
+            result.vcards
+        );
+        let card = |name: &str| Some(String::from(name));
+        assert_eq!(
+            vec![
+                (
+                    Some(1),
+                    ErrorKind::UnexpectedFieldError(String::from("contents")),
+                    None
+                ),
+                (
+                    Some(11),
+                    ErrorKind::InvalidBDayError(String::from("input contains invalid characters")),
+                    card("Bob Test")
+                ),
+                (
+                    Some(13),
+                    ErrorKind::UnexpectedFieldError(String::from("END:VCARD")),
+                    None
+                ),
+                (
+                    Some(17),
+                    ErrorKind::UnexpectedFieldError(String::from("BEGIN:VCARD")),
+                    card("Charlie Test")
+                ),
+                (
+                    Some(20),
+                    ErrorKind::InvalidPropertyError(String::from(
+                        "missing ':': this is not a content line"
+                    )),
+                    card("Dave Test")
+                ),
+                (Some(26), ErrorKind::MissingEndError, card("Mallory Test")),
+            ],
             result
+                .diagnostics
+                .into_iter()
+                .map(|d| (d.line, d.kind, d.card))
+                .collect::<Vec<_>>()
         );
     }
+
+    #[test]
+    fn parse_vcards_lenient_ok() {
+        let input = "\
+BEGIN:VCARD
+VERSION:2.1
+FN:Allice Test
+END:VCARD
+";
+
+        let result = parse_vcards_lenient(input);
+
+        assert_eq!(
+            ParsedVCards {
+                vcards: vec![VCard {
+                    name: String::from("Allice Test"),
+                    ..Default::default()
+                }],
+                diagnostics: vec![],
+            },
+            result
+        );
+    }
+
+    #[test]
+    fn parse_vcards_error_location() {
+        let input = "\
+BEGIN:VCARD\r
+VERSION:4.0\r
+UID:urn:uuid:1234\r
+NOTE:a folded\r
+  note\r
+BDAY:1980-\r
+ asdf-07\r
+FN:Bob Test\r
+END:VCARD\r
+";
+
+        let result = parse_vcards(input).unwrap_err();
+
+        assert_eq!(
+            VCardError {
+                kind: ErrorKind::InvalidBDayError(String::from(
+                    "input contains invalid characters"
+                )),
+                line: Some(6),
+                column: Some(6),
+                snippet: Some(String::from("BDAY:1980-asdf-07")),
+                card: Some(String::from("urn:uuid:1234")),
+            },
+            result
+        );
+        assert_eq!(
+            "\
+error while parsing bday: input contains invalid characters
+ --> line 6, column 6 (vcard \"urn:uuid:1234\")
+  |
+6 | BDAY:1980-asdf-07
+  |      ^",
+            result.to_string()
+        );
+    }
+
+    #[test]
+    fn parse_vcards_error_location_multiline_quoted_printable() {
+        let input = format!(
+            "\
+BEGIN:VCARD
+VERSION:2.1
+FN;CHARSET=X-UNKNOWN;ENCODING=QUOTED-PRINTABLE:=41=
+{}
+END:VCARD
+",
+            "=42=43".repeat(20)
+        );
+
+        let error = parse_vcards(&input).unwrap_err();
+
+        assert!(matches!(error.kind, ErrorKind::InvalidEncodingError(_)));
+        assert_eq!(Some(3), error.line);
+        assert_eq!(Some(48), error.column);
+
+        let parsed = parse_vcards_lenient(&input);
+
+        assert!(parsed.vcards.is_empty());
+        assert_eq!(vec![error], parsed.diagnostics);
+    }
+
+    #[test]
+    fn parse_vcards_error_location_property() {
+        let input = "\
+BEGIN:VCARD
+VERSION:4.0
+FN:Bob Test
+TEL;TYPE=\"cell:+01234567890
+END:VCARD
+";
+
+        let result = parse_vcards(input).unwrap_err();
+
+        assert_eq!(Some(4), result.line);
+        assert_eq!(Some(10), result.column);
+        assert_eq!(Some(String::from("Bob Test")), result.card);
+    }
+
+    #[test]
+    fn parse_vcards_error_location_missing_name() {
+        let input = "\
+BEGIN:VCARD
+VERSION:4.0
+FN:Allice Test
+END:VCARD
+BEGIN:VCARD
+VERSION:4.0
+TEL:+01234567890
+END:VCARD
+";
+
+        let result = parse_vcards(input).unwrap_err();
+
+        assert_eq!(ErrorKind::NoNameError, result.kind);
+        assert_eq!(Some(5), result.line);
+        assert_eq!(Some(String::from("BEGIN:VCARD")), result.snippet);
+    }
 }
@@ -0,0 +1,601 @@
+use super::encoding::escape_text;
+use super::{ErrorKind, ParsedVCards, Property, VCard, VCardError};
+use ::csv::{ReaderBuilder, StringRecord};
+use chrono::NaiveDate;
+use std::str::FromStr;
+
+/// Columns of a CSV export to read contacts from. Columns are found by their
+/// header, ignoring case.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ColumnMapping {
+    /// The full name, otherwise it's put together from the name parts.
+    pub name: Option<String>,
+    pub given_name: Option<String>,
+    pub additional_name: Option<String>,
+    pub family_name: Option<String>,
+    pub organization: Option<String>,
+    pub birthday: Option<String>,
+    pub anniversary: Option<String>,
+    /// Format of dates that aren't in ISO 8601 form like `1980-05-07` or
+    /// `--05-07`, e.g. `%d.%m.%Y` (see `chrono::format::strftime`).
+    pub date_format: Option<String>,
+    /// Separator of the fields, otherwise guessed from the header.
+    pub delimiter: Option<u8>,
+}
+
+impl ColumnMapping {
+    /// The layout of Google Contacts exports.
+    fn google() -> Self {
+        ColumnMapping {
+            given_name: Some(String::from("First Name")),
+            additional_name: Some(String::from("Middle Name")),
+            family_name: Some(String::from("Last Name")),
+            organization: Some(String::from("Organization Name")),
+            birthday: Some(String::from("Birthday")),
+            ..Default::default()
+        }
+    }
+
+    /// The layout of Google Contacts exports before 2024 ("Google CSV").
+    fn google_legacy() -> Self {
+        ColumnMapping {
+            name: Some(String::from("Name")),
+            given_name: Some(String::from("Given Name")),
+            additional_name: Some(String::from("Additional Name")),
+            family_name: Some(String::from("Family Name")),
+            organization: Some(String::from("Organization 1 - Name")),
+            birthday: Some(String::from("Birthday")),
+            ..Default::default()
+        }
+    }
+
+    /// The layout of Outlook exports, with US dates like `5/7/1980`.
+    fn outlook() -> Self {
+        ColumnMapping {
+            given_name: Some(String::from("First Name")),
+            additional_name: Some(String::from("Middle Name")),
+            family_name: Some(String::from("Last Name")),
+            organization: Some(String::from("Company")),
+            birthday: Some(String::from("Birthday")),
+            anniversary: Some(String::from("Anniversary")),
+            date_format: Some(String::from("%m/%d/%Y")),
+            ..Default::default()
+        }
+    }
+
+    /// Whether any column is mapped, rather than only the format.
+    fn has_columns(&self) -> bool {
+        [
+            &self.name,
+            &self.given_name,
+            &self.additional_name,
+            &self.family_name,
+            &self.organization,
+            &self.birthday,
+            &self.anniversary,
+        ]
+        .iter()
+        .any(|column| column.is_some())
+    }
+
+    /// Recognizes the layout of Google and Outlook exports by their headers.
+    fn detect(headers: &StringRecord) -> Option<Self> {
+        let has = |header| find_column(headers, header).is_some();
+        if has("Anniversary") && has("First Name") {
+            Some(Self::outlook())
+        } else if has("Given Name") {
+            Some(Self::google_legacy())
+        } else if has("First Name") {
+            Some(Self::google())
+        } else {
+            None
+        }
+    }
+}
+
+/// Parses a mapping like `name=Full Name;birthday=Date of Birth;date_format=%d.%m.%Y`.
+/// The keys are the names of the fields. The `delimiter` is a character, or
+/// `comma`, `semicolon` or `tab`.
+impl FromStr for ColumnMapping {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut mapping = ColumnMapping::default();
+        for pair in s.split(';').filter(|pair| !pair.trim().is_empty()) {
+            let (key, value) = pair
+                .split_once('=')
+                .ok_or_else(|| format!("expected key=column, got {}", pair))?;
+            if key.trim() == "delimiter" {
+                mapping.delimiter = Some(match value.trim() {
+                    "comma" => b',',
+                    "semicolon" => b';',
+                    "tab" => b'\t',
+                    value if value.len() == 1 => value.as_bytes()[0],
+                    value => return Err(format!("invalid delimiter {}", value)),
+                });
+                continue;
+            }
+            let field = match key.trim() {
+                "name" => &mut mapping.name,
+                "given_name" => &mut mapping.given_name,
+                "additional_name" => &mut mapping.additional_name,
+                "family_name" => &mut mapping.family_name,
+                "organization" => &mut mapping.organization,
+                "birthday" => &mut mapping.birthday,
+                "anniversary" => &mut mapping.anniversary,
+                "date_format" => &mut mapping.date_format,
+                key => return Err(format!("unknown key {}", key)),
+            };
+            *field = Some(String::from(value.trim()));
+        }
+        Ok(mapping)
+    }
+}
+
+/// Parses the contacts of a CSV export, one per row, skipping broken rows.
+/// Without a `mapping` of columns the layout of Google and Outlook exports is
+/// detected, a `date_format` or `delimiter` of the mapping is used anyway.
+///
+/// Google's numbered `Event 1 - Label`/`Event 1 - Value` columns are read as
+/// events in any case.
+pub fn parse_csv(contents: impl AsRef<[u8]>, mapping: Option<&ColumnMapping>) -> ParsedVCards {
+    let mut parsed = ParsedVCards::default();
+    let contents = decode(contents.as_ref());
+    let delimiter = mapping
+        .and_then(|mapping| mapping.delimiter)
+        .unwrap_or_else(|| sniff_delimiter(&contents));
+    let mut reader = ReaderBuilder::new()
+        .flexible(true)
+        .delimiter(delimiter)
+        .from_reader(contents.as_bytes());
+
+    let headers = match reader.headers() {
+        Ok(headers) => headers.clone(),
+        Err(e) => {
+            parsed.diagnostics.push(invalid(e.to_string()));
+            return parsed;
+        }
+    };
+    // columns of the known layouts may be missing, e.g. in older exports
+    let (mapping, required) = match mapping {
+        Some(mapping) if mapping.has_columns() => (mapping.clone(), true),
+        _ => match ColumnMapping::detect(&headers) {
+            Some(detected) => {
+                let date_format = mapping.and_then(|mapping| mapping.date_format.clone());
+                (
+                    ColumnMapping {
+                        date_format: date_format.or(detected.date_format),
+                        ..detected
+                    },
+                    false,
+                )
+            }
+            None => {
+                parsed.diagnostics.push(invalid(
+                    "unknown columns, expected an export of Google Contacts or Outlook",
+                ));
+                return parsed;
+            }
+        },
+    };
+    let columns = match Columns::find(&headers, &mapping, required) {
+        Ok(columns) => columns,
+        Err(e) => {
+            parsed.diagnostics.push(e);
+            return parsed;
+        }
+    };
+
+    for record in reader.records() {
+        let result = record
+            .map_err(|e| invalid(e.to_string()))
+            .and_then(|record| {
+                let line = record.position().map_or(0, |position| position.line());
+                columns
+                    .read_row(&record, mapping.date_format.as_deref())
+                    .map_err(|e| e.at(line as usize, &record.iter().collect::<Vec<_>>().join(",")))
+            });
+        match result {
+            Ok(Some(vcard)) => parsed.vcards.push(vcard),
+            Ok(None) => (),
+            Err(e) => parsed.diagnostics.push(e),
+        }
+    }
+    parsed
+}
+
+/// Indices of the mapped columns.
+struct Columns {
+    name: Option<usize>,
+    given_name: Option<usize>,
+    additional_name: Option<usize>,
+    family_name: Option<usize>,
+    organization: Option<usize>,
+    birthday: Option<usize>,
+    anniversary: Option<usize>,
+    /// Label and value columns of Google's events.
+    events: Vec<(Option<usize>, usize)>,
+}
+
+impl Columns {
+    fn find(
+        headers: &StringRecord,
+        mapping: &ColumnMapping,
+        required: bool,
+    ) -> Result<Self, VCardError> {
+        let column = |header: &Option<String>| match header {
+            Some(header) => match find_column(headers, header) {
+                Some(column) => Ok(Some(column)),
+                None if required => Err(invalid(format!("no column {}", header))),
+                None => Ok(None),
+            },
+            None => Ok(None),
+        };
+
+        let mut events = Vec::new();
+        for n in 1.. {
+            let Some(value) = find_column(headers, &format!("Event {} - Value", n)) else {
+                break;
+            };
+            let label = find_column(headers, &format!("Event {} - Label", n))
+                .or_else(|| find_column(headers, &format!("Event {} - Type", n)));
+            events.push((label, value));
+        }
+
+        Ok(Columns {
+            name: column(&mapping.name)?,
+            given_name: column(&mapping.given_name)?,
+            additional_name: column(&mapping.additional_name)?,
+            family_name: column(&mapping.family_name)?,
+            organization: column(&mapping.organization)?,
+            birthday: column(&mapping.birthday)?,
+            anniversary: column(&mapping.anniversary)?,
+            events,
+        })
+    }
+
+    /// Converts the row to the properties of a vCard, so that it's read just
+    /// like one. Empty rows are skipped.
+    fn read_row(
+        &self,
+        record: &StringRecord,
+        date_format: Option<&str>,
+    ) -> Result<Option<VCard>, VCardError> {
+        if record.iter().all(|field| field.trim().is_empty()) {
+            return Ok(None);
+        }
+        let field = |column: Option<usize>| {
+            column
+                .and_then(|column| record.get(column))
+                .map(str::trim)
+                .filter(|field| !field.is_empty())
+        };
+
+        let mut properties = Vec::new();
+        if let Some(name) = field(self.name) {
+            properties.push(property(None, "FN", &escape_text(name)));
+        }
+        let name_parts = [self.family_name, self.given_name, self.additional_name].map(field);
+        if name_parts.iter().any(Option::is_some) {
+            let value = name_parts
+                .map(|part| escape_text(part.unwrap_or_default()))
+                .join(";");
+            properties.push(property(None, "N", &format!("{};;", value)));
+        }
+        if let Some(organization) = field(self.organization) {
+            properties.push(property(None, "ORG", &escape_text(organization)));
+        }
+        if let Some(birthday) = field(self.birthday).and_then(|d| date_value(d, date_format)) {
+            properties.push(property(None, "BDAY", &birthday));
+        }
+        if let Some(anniversary) = field(self.anniversary).and_then(|d| date_value(d, date_format))
+        {
+            properties.push(property(None, "ANNIVERSARY", &anniversary));
+        }
+        for (n, (label, value)) in self.events.iter().enumerate() {
+            let Some(date) = field(Some(*value)).and_then(|d| date_value(d, date_format)) else {
+                continue;
+            };
+            // Google marks its predefined labels with "* "
+            let label = field(*label).map(|label| label.trim_start_matches("* "));
+            let group = format!("item{}", n + 1);
+            match label {
+                Some(label) if label.eq_ignore_ascii_case("anniversary") => {
+                    properties.push(property(None, "ANNIVERSARY", &date));
+                }
+                Some(label) if !label.eq_ignore_ascii_case("other") => {
+                    properties.push(property(Some(&group), "X-ABDATE", &date));
+                    properties.push(property(Some(&group), "X-ABLABEL", &escape_text(label)));
+                }
+                _ => properties.push(property(Some(&group), "X-ABDATE", &date)),
+            }
+        }
+
+        VCard::from_properties(&properties)
+            .map(Some)
+            .map_err(|e| e.in_card(field(self.name).map(String::from)))
+    }
+}
+
+fn find_column(headers: &StringRecord, header: &str) -> Option<usize> {
+    headers
+        .iter()
+        .position(|h| h.trim().eq_ignore_ascii_case(header))
+}
+
+fn property(group: Option<&str>, name: &str, value: &str) -> Property {
+    Property {
+        group: group.map(String::from),
+        name: String::from(name),
+        params: Vec::new(),
+        value: String::from(value),
+    }
+}
+
+/// Converts a date to the form of a vCard value, `None` for placeholders of
+/// empty dates like Outlook's `0/0/00`. Dates not matching `date_format` are
+/// left to the vCard date parser.
+fn date_value(date: &str, date_format: Option<&str>) -> Option<String> {
+    if date.chars().all(|c| c == '0' || !c.is_ascii_digit()) {
+        return None;
+    }
+    let parsed = date_format.and_then(|format| NaiveDate::parse_from_str(date, format).ok());
+    Some(match parsed {
+        Some(parsed) => parsed.format("%Y-%m-%d").to_string(),
+        None => escape_text(date),
+    })
+}
+
+/// Guesses the delimiter by the header: `,`, or `;` as written where the
+/// comma is the decimal separator, or a tab.
+fn sniff_delimiter(contents: &str) -> u8 {
+    let header = contents.lines().next().unwrap_or_default();
+    let count = |delimiter: u8| {
+        let mut quoted = false;
+        header
+            .bytes()
+            .filter(|&b| {
+                if b == b'"' {
+                    quoted = !quoted;
+                }
+                !quoted && b == delimiter
+            })
+            .count()
+    };
+    let mut delimiter = b',';
+    for other in [b';', b'\t'] {
+        if count(other) > count(delimiter) {
+            delimiter = other;
+        }
+    }
+    delimiter
+}
+
+/// Decodes the file by its BOM, as UTF-8, or else as Windows-1252, which
+/// Outlook uses for its exports on western systems.
+fn decode(contents: &[u8]) -> String {
+    if let Some((encoding, _)) = encoding_rs::Encoding::for_bom(contents) {
+        return encoding.decode_with_bom_removal(contents).0.into_owned();
+    }
+    match std::str::from_utf8(contents) {
+        Ok(contents) => String::from(contents),
+        Err(_) => encoding_rs::WINDOWS_1252.decode(contents).0.into_owned(),
+    }
+}
+
+fn invalid(msg: impl Into<String>) -> VCardError {
+    ErrorKind::InvalidCsvError(msg.into()).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vcard::{DateAndOrTime, Event, EventKind, StructuredName};
+
+    fn date(year: i32, month: u32, day: u32) -> DateAndOrTime {
+        DateAndOrTime::Date(NaiveDate::from_ymd_opt(year, month, day).unwrap())
+    }
+
+    fn names(parsed: &ParsedVCards) -> Vec<&str> {
+        parsed
+            .vcards
+            .iter()
+            .map(|vcard| vcard.name.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn parse_google_csv() {
+        let input = "\
+First Name,Middle Name,Last Name,Nickname,Organization Name,Birthday,Labels,Event 1 - Label,Event 1 - Value,Event 2 - Label,Event 2 - Value
+Allice,,Test,,,1980-05-07,* myContacts,Anniversary,2010-08-01,name day,--12-06
+Bob,,\"Test, Jr.\",,,--06-08,* myContacts,,,,
+,,,,Test Inc.,,* myContacts,Other,2001-02-03,,
+";
+
+        let parsed = parse_csv(input, None);
+
+        assert!(parsed.diagnostics.is_empty(), "{:?}", parsed.diagnostics);
+        assert_eq!(
+            vec![
+                VCard {
+                    name: String::from("Allice Test"),
+                    structured_name: Some(StructuredName {
+                        family: vec![String::from("Test")],
+                        given: vec![String::from("Allice")],
+                        ..Default::default()
+                    }),
+                    bday: Some(date(1980, 5, 7)),
+                    events: vec![
+                        Event {
+                            kind: EventKind::Anniversary,
+                            date: date(2010, 8, 1),
+                        },
+                        Event {
+                            kind: EventKind::Custom(String::from("name day")),
+                            date: DateAndOrTime::Partial {
+                                year: None,
+                                month: Some(12),
+                                day: Some(6)
+                            },
+                        },
+                    ],
+                    ..Default::default()
+                },
+                VCard {
+                    name: String::from("Bob Test, Jr."),
+                    structured_name: Some(StructuredName {
+                        family: vec![String::from("Test, Jr.")],
+                        given: vec![String::from("Bob")],
+                        ..Default::default()
+                    }),
+                    bday: Some(DateAndOrTime::Partial {
+                        year: None,
+                        month: Some(6),
+                        day: Some(8)
+                    }),
+                    ..Default::default()
+                },
+                VCard {
+                    name: String::from("Test Inc."),
+                    events: vec![Event {
+                        kind: EventKind::Other,
+                        date: date(2001, 2, 3),
+                    }],
+                    ..Default::default()
+                },
+            ],
+            parsed.vcards
+        );
+    }
+
+    #[test]
+    fn parse_google_legacy_csv() {
+        let input = "\
+Name,Given Name,Additional Name,Family Name,Birthday,Event 1 - Type,Event 1 - Value
+Allice Test,Allice,,Test,1980-05-07,* Anniversary,2010-08-01
+";
+
+        let parsed = parse_csv(input, None);
+
+        assert!(parsed.diagnostics.is_empty(), "{:?}", parsed.diagnostics);
+        assert_eq!(vec!["Allice Test"], names(&parsed));
+        assert_eq!(Some(date(1980, 5, 7)), parsed.vcards[0].bday);
+        assert_eq!(EventKind::Anniversary, parsed.vcards[0].events[0].kind);
+    }
+
+    #[test]
+    fn parse_outlook_csv() {
+        // Outlook writes Windows-1252
+        let mut input = b"\
+\"Title\",\"First Name\",\"Middle Name\",\"Last Name\",\"Anniversary\",\"Birthday\"
+\"\",\"J\xfcrgen\",\"\",\"Test\",\"8/1/2010\",\"5/7/1980\"
+\"\",\"Bob\",\"\",\"Test\",\"0/0/00\",\"0/0/00\"
+"
+        .to_vec();
+        input.extend_from_slice(b"\"\",\"Carl\",\"\",\"Test\",\"\",\"13/13/1980\"\r\n");
+
+        let parsed = parse_csv(input, None);
+
+        assert_eq!(vec!["J\u{fc}rgen Test", "Bob Test"], names(&parsed));
+        assert_eq!(Some(date(1980, 5, 7)), parsed.vcards[0].bday);
+        assert_eq!(
+            vec![Event {
+                kind: EventKind::Anniversary,
+                date: date(2010, 8, 1),
+            }],
+            parsed.vcards[0].events
+        );
+        assert_eq!(None, parsed.vcards[1].bday);
+        assert!(parsed.vcards[1].events.is_empty());
+
+        assert_eq!(1, parsed.diagnostics.len());
+        assert!(matches!(
+            parsed.diagnostics[0].kind,
+            ErrorKind::InvalidBDayError(_)
+        ));
+        assert_eq!(Some(4), parsed.diagnostics[0].line);
+    }
+
+    #[test]
+    fn parse_csv_custom_mapping() {
+        let input = "\
+Full Name;Date of Birth
+Allice Test;07.05.1980
+\"Test; Bob\";08.06.1981
+";
+        let mapping: ColumnMapping = "name=full name; birthday=Date of Birth;date_format=%d.%m.%Y"
+            .parse()
+            .unwrap();
+
+        let parsed = parse_csv(input, Some(&mapping));
+
+        assert!(parsed.diagnostics.is_empty(), "{:?}", parsed.diagnostics);
+        assert_eq!(vec!["Allice Test", "Test; Bob"], names(&parsed));
+        assert_eq!(Some(date(1980, 5, 7)), parsed.vcards[0].bday);
+
+        let mapping: ColumnMapping = "name=Name".parse().unwrap();
+        let parsed = parse_csv(input, Some(&mapping));
+        assert_eq!(
+            vec![ErrorKind::InvalidCsvError(String::from("no column Name"))],
+            parsed
+                .diagnostics
+                .into_iter()
+                .map(|d| d.kind)
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn parse_csv_delimiter() {
+        let input =
+            "Full Name\tDate of Birth\tCity, Region, Country\nAllice Test\t1980-05-07\tVienna, Vienna, Austria\n";
+        let mapping: ColumnMapping = "name=Full Name;birthday=Date of Birth;delimiter=tab"
+            .parse()
+            .unwrap();
+
+        let parsed = parse_csv(input, Some(&mapping));
+
+        assert!(parsed.diagnostics.is_empty(), "{:?}", parsed.diagnostics);
+        assert_eq!(vec!["Allice Test"], names(&parsed));
+        assert_eq!(Some(b'\t'), mapping.delimiter);
+        assert_eq!(
+            Some(b'|'),
+            "delimiter=|".parse::<ColumnMapping>().unwrap().delimiter
+        );
+    }
+
+    #[test]
+    fn parse_outlook_csv_date_format() {
+        // e.g. exported by a German Outlook
+        let input = "\
+\"Vorname\";\"First Name\";\"Last Name\";\"Anniversary\";\"Birthday\"
+\"\";\"Allice\";\"Test\";\"1.8.2010\";\"7.5.1980\"
+";
+        let mapping: ColumnMapping = "date_format=%d.%m.%Y".parse().unwrap();
+
+        let parsed = parse_csv(input, Some(&mapping));
+
+        assert!(parsed.diagnostics.is_empty(), "{:?}", parsed.diagnostics);
+        assert_eq!(vec!["Allice Test"], names(&parsed));
+        assert_eq!(Some(date(1980, 5, 7)), parsed.vcards[0].bday);
+        assert_eq!(date(2010, 8, 1), parsed.vcards[0].events[0].date);
+    }
+
+    #[test]
+    fn parse_column_mapping_invalid() {
+        assert!("name".parse::<ColumnMapping>().is_err());
+        assert!("phone=Phone".parse::<ColumnMapping>().is_err());
+        assert!("delimiter=::".parse::<ColumnMapping>().is_err());
+    }
+
+    #[test]
+    fn parse_csv_unknown_layout() {
+        let parsed = parse_csv("Phone,E-mail\n+0123,a@example.com\n", None);
+        assert!(parsed.vcards.is_empty());
+        assert!(matches!(
+            parsed.diagnostics[0].kind,
+            ErrorKind::InvalidCsvError(_)
+        ));
+    }
+}
@@ -0,0 +1,333 @@
+use chrono::prelude::*;
+use std::fmt;
+
+/// Value of a date property like `BDAY`, covering the forms allowed by
+/// vCard 3.0 and 4.0 (RFC 6350 section 4.3).
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum DateAndOrTime {
+    /// A complete date, e.g. `19800507` or `1980-05-07`.
+    Date(NaiveDate),
+    /// A date with reduced accuracy or truncated, e.g. `--0507` (no year),
+    /// `1980-05` (no day) or `---07` (only the day).
+    Partial {
+        year: Option<i32>,
+        month: Option<u32>,
+        day: Option<u32>,
+    },
+    /// A complete date with a time, e.g. `19800507T120000Z`. The time zone is
+    /// dropped, a birthday happens on the same calendar day everywhere.
+    DateTime(NaiveDateTime),
+    /// Free text, given with `VALUE=text`, e.g. `circa 1800`.
+    Text(String),
+}
+
+impl DateAndOrTime {
+    /// Parses a date or date-time value in basic (`19800507`) or extended
+    /// (`1980-05-07`) format.
+    pub fn parse(value: &str) -> Result<Self, String> {
+        if !value.is_ascii() {
+            return Err(format!("invalid date {}", value));
+        }
+        let (date, time) = match value.split_once('T') {
+            Some((date, time)) => (date, Some(time)),
+            None => (value, None),
+        };
+        let time = time.map(parse_time).transpose()?;
+
+        if let Some(truncated) = date.strip_prefix("--") {
+            return parse_truncated(truncated);
+        }
+
+        let date = match date.len() {
+            4 => {
+                return Ok(Self::Partial {
+                    year: Some(parse_number(date)?),
+                    month: None,
+                    day: None,
+                })
+            }
+            7 if date.as_bytes()[4] == b'-' => {
+                return Ok(Self::Partial {
+                    year: Some(parse_number(&date[..4])?),
+                    month: Some(parse_month(&date[5..])?),
+                    day: None,
+                })
+            }
+            8 => NaiveDate::parse_from_str(date, "%Y%m%d"),
+            _ => NaiveDate::parse_from_str(date, "%Y-%m-%d"),
+        }
+        .map_err(|e| e.to_string())?;
+
+        Ok(match time {
+            Some(time) => Self::DateTime(date.and_time(time)),
+            None => Self::Date(date),
+        })
+    }
+
+    pub fn year(&self) -> Option<i32> {
+        match self {
+            Self::Date(date) => Some(date.year()),
+            Self::Partial { year, .. } => *year,
+            Self::DateTime(date_time) => Some(date_time.year()),
+            Self::Text(_) => None,
+        }
+    }
+
+    /// Month and day, if both are known.
+    pub fn month_day(&self) -> Option<(u32, u32)> {
+        match self {
+            Self::Date(date) => Some((date.month(), date.day())),
+            Self::Partial {
+                month: Some(month),
+                day: Some(day),
+                ..
+            } => Some((*month, *day)),
+            Self::DateTime(date_time) => Some((date_time.month(), date_time.day())),
+            _ => None,
+        }
+    }
+
+    /// Drops the year, used for dates where the exporter had to fill in a
+    /// placeholder year (e.g. Apple's `X-APPLE-OMIT-YEAR`).
+    pub fn without_year(self) -> Self {
+        match self.month_day() {
+            Some((month, day)) => Self::Partial {
+                year: None,
+                month: Some(month),
+                day: Some(day),
+            },
+            None => self,
+        }
+    }
+
+    /// Whether the yearly recurrence of this date falls on `date`. February
+    /// 29 recurs on February 28 in common years.
+    pub fn is_anniversary_on(&self, date: NaiveDate) -> bool {
+        match self.month_day() {
+            Some((2, 29)) if !date.leap_year() => (date.month(), date.day()) == (2, 28),
+            month_day => month_day == Some((date.month(), date.day())),
+        }
+    }
+}
+
+/// Formats the date in the extended format of ISO 8601, e.g. `1980-05-07`,
+/// `--05-07` or `1980-05-07T12:00:00`.
+impl fmt::Display for DateAndOrTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Date(date) => write!(f, "{}", date.format("%Y-%m-%d")),
+            Self::Partial { year, month, day } => match (year, month, day) {
+                (Some(year), Some(month), Some(day)) => {
+                    write!(f, "{:04}-{:02}-{:02}", year, month, day)
+                }
+                (Some(year), Some(month), None) => write!(f, "{:04}-{:02}", year, month),
+                (Some(year), None, _) => write!(f, "{:04}", year),
+                (None, Some(month), Some(day)) => write!(f, "--{:02}-{:02}", month, day),
+                (None, Some(month), None) => write!(f, "--{:02}", month),
+                (None, None, Some(day)) => write!(f, "---{:02}", day),
+                (None, None, None) => Ok(()),
+            },
+            Self::DateTime(date_time) => write!(f, "{}", date_time.format("%Y-%m-%dT%H:%M:%S")),
+            Self::Text(text) => write!(f, "{}", text),
+        }
+    }
+}
+
+impl From<NaiveDate> for DateAndOrTime {
+    fn from(date: NaiveDate) -> Self {
+        Self::Date(date)
+    }
+}
+
+/// Parses the part after `--`, i.e. `MMDD`, `MM-DD`, `MM` or `-DD`.
+fn parse_truncated(value: &str) -> Result<DateAndOrTime, String> {
+    let (month, day) = match value.len() {
+        2 => (Some(parse_month(value)?), None),
+        3 if value.starts_with('-') => (None, Some(parse_number(&value[1..])?)),
+        4 => (
+            Some(parse_month(&value[..2])?),
+            Some(parse_number(&value[2..])?),
+        ),
+        5 if value.as_bytes()[2] == b'-' => (
+            Some(parse_month(&value[..2])?),
+            Some(parse_number(&value[3..])?),
+        ),
+        _ => return Err(format!("invalid truncated date --{}", value)),
+    };
+
+    if let (Some(month), Some(day)) = (month, day) {
+        // 2000 is a leap year, so --0229 is accepted
+        if NaiveDate::from_ymd_opt(2000, month, day).is_none() {
+            return Err(format!("invalid truncated date --{}", value));
+        }
+    } else if day.is_some_and(|day| !(1..=31).contains(&day)) {
+        return Err(format!("invalid truncated date --{}", value));
+    }
+
+    Ok(DateAndOrTime::Partial {
+        year: None,
+        month,
+        day,
+    })
+}
+
+/// Parses a time like `120000`, `12:00:00`, `1200` or `12`, with an optional
+/// zone designator (`Z`, `+01`, `+0100`, `+01:00`).
+fn parse_time(value: &str) -> Result<NaiveTime, String> {
+    let time = match value.find(['Z', '+', '-']) {
+        Some(i) => {
+            let zone = &value[i..];
+            let valid_zone = zone == "Z"
+                || (zone.len() >= 3 && zone[1..].chars().all(|c| c.is_ascii_digit() || c == ':'));
+            if !valid_zone {
+                return Err(format!("invalid time zone {}", zone));
+            }
+            &value[..i]
+        }
+        None => value,
+    };
+
+    let format = match time.len() {
+        2 => {
+            return NaiveTime::from_hms_opt(parse_number(time)?, 0, 0)
+                .ok_or_else(|| format!("invalid time {}", value))
+        }
+        4 => "%H%M",
+        5 => "%H:%M",
+        6 => "%H%M%S",
+        8 => "%H:%M:%S",
+        _ => return Err(format!("invalid time {}", value)),
+    };
+    NaiveTime::parse_from_str(time, format).map_err(|e| e.to_string())
+}
+
+fn parse_month(value: &str) -> Result<u32, String> {
+    match parse_number(value)? {
+        month @ 1..=12 => Ok(month),
+        month => Err(format!("invalid month {}", month)),
+    }
+}
+
+fn parse_number<T: std::str::FromStr>(value: &str) -> Result<T, String> {
+    if !value.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(format!("invalid number {}", value));
+    }
+    value
+        .parse()
+        .map_err(|_| format!("invalid number {}", value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    fn partial(year: Option<i32>, month: Option<u32>, day: Option<u32>) -> DateAndOrTime {
+        DateAndOrTime::Partial { year, month, day }
+    }
+
+    #[test]
+    fn parse_dates() {
+        let expected = DateAndOrTime::Date(date(1980, 5, 7));
+        assert_eq!(Ok(expected.clone()), DateAndOrTime::parse("1980-05-07"));
+        assert_eq!(Ok(expected), DateAndOrTime::parse("19800507"));
+    }
+
+    #[test]
+    fn parse_partial_dates() {
+        let cases = [
+            ("--0507", partial(None, Some(5), Some(7))),
+            ("--05-07", partial(None, Some(5), Some(7))),
+            ("--0229", partial(None, Some(2), Some(29))),
+            ("--05", partial(None, Some(5), None)),
+            ("---07", partial(None, None, Some(7))),
+            ("1980", partial(Some(1980), None, None)),
+            ("1980-05", partial(Some(1980), Some(5), None)),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(Ok(expected), DateAndOrTime::parse(input), "{}", input);
+        }
+    }
+
+    #[test]
+    fn parse_date_times() {
+        let expected = DateAndOrTime::DateTime(date(1980, 5, 7).and_hms_opt(0, 0, 0).unwrap());
+        for input in [
+            "1980-05-07T00:00:00Z",
+            "19800507T000000",
+            "19800507T0000+0100",
+            "1980-05-07T00-05:00",
+            "19800507T00",
+        ] {
+            assert_eq!(
+                Ok(expected.clone()),
+                DateAndOrTime::parse(input),
+                "{}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn parse_invalid() {
+        for input in [
+            "1980-asdf-07",
+            "1980-13-07",
+            "--1307",
+            "--0230",
+            "---32",
+            "19800507T25",
+            "19800507T1200X",
+            "circa 1800",
+        ] {
+            assert!(DateAndOrTime::parse(input).is_err(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn anniversary_on() {
+        let today = date(2024, 5, 7);
+        assert!(DateAndOrTime::Date(date(1980, 5, 7)).is_anniversary_on(today));
+        assert!(partial(None, Some(5), Some(7)).is_anniversary_on(today));
+        assert!(!partial(None, Some(5), None).is_anniversary_on(today));
+        assert!(!partial(None, Some(5), Some(8)).is_anniversary_on(today));
+        assert!(!DateAndOrTime::Text(String::from("circa 1800")).is_anniversary_on(today));
+    }
+
+    #[test]
+    fn anniversary_on_leap_day() {
+        let leap_day = DateAndOrTime::Date(date(1980, 2, 29));
+        assert!(leap_day.is_anniversary_on(date(2024, 2, 29)));
+        assert!(!leap_day.is_anniversary_on(date(2024, 2, 28)));
+        assert!(leap_day.is_anniversary_on(date(2023, 2, 28)));
+        assert!(!leap_day.is_anniversary_on(date(2023, 3, 1)));
+        assert!(partial(None, Some(2), Some(29)).is_anniversary_on(date(2023, 2, 28)));
+        assert!(DateAndOrTime::Date(date(1981, 2, 28)).is_anniversary_on(date(2023, 2, 28)));
+    }
+
+    #[test]
+    fn display() {
+        assert_eq!(
+            "1980-05-07",
+            DateAndOrTime::Date(date(1980, 5, 7)).to_string()
+        );
+        assert_eq!("--05-07", partial(None, Some(5), Some(7)).to_string());
+        assert_eq!("1980-05", partial(Some(1980), Some(5), None).to_string());
+        assert_eq!("---07", partial(None, None, Some(7)).to_string());
+        assert_eq!(
+            "1980-05-07T12:30:00",
+            DateAndOrTime::DateTime(date(1980, 5, 7).and_hms_opt(12, 30, 0).unwrap()).to_string()
+        );
+    }
+
+    #[test]
+    fn without_year() {
+        assert_eq!(
+            partial(None, Some(5), Some(7)),
+            DateAndOrTime::Date(date(1604, 5, 7)).without_year()
+        );
+    }
+}
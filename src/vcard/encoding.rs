@@ -0,0 +1,221 @@
+use encoding_rs::Encoding;
+
+/// Decodes quoted-printable text (RFC 2045 section 6.7). Soft line breaks
+/// are expected to be joined already, a remaining `=` that doesn't start a
+/// valid `=XX` sequence is kept as is.
+pub fn decode_quoted_printable(encoded: &str) -> Vec<u8> {
+    let bytes = encoded.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], hex) {
+            (b'=', Some(b)) => {
+                decoded.push(b);
+                i += 3;
+            }
+            (b, _) => {
+                decoded.push(b);
+                i += 1;
+            }
+        }
+    }
+    decoded
+}
+
+/// Decodes base64 (RFC 4648), ignoring whitespace left over from folding.
+pub fn decode_base64(encoded: &str) -> Result<Vec<u8>, String> {
+    let mut decoded = Vec::with_capacity(encoded.len() / 4 * 3);
+    let mut buffer: u32 = 0;
+    let mut bits = 0;
+    for c in encoded.bytes().filter(|c| !c.is_ascii_whitespace()) {
+        let sextet = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            b'=' => break,
+            _ => return Err(format!("invalid base64 character '{}'", c as char)),
+        };
+        buffer = (buffer << 6) | u32::from(sextet);
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            decoded.push((buffer >> bits) as u8);
+        }
+    }
+    Ok(decoded)
+}
+
+/// Converts bytes in the given charset (e.g. `ISO-8859-1`, `WINDOWS-1252`)
+/// to a string, UTF-8 is assumed without a charset.
+pub fn decode_charset(bytes: Vec<u8>, charset: Option<&str>) -> Result<String, String> {
+    let encoding = match charset {
+        Some(label) => Encoding::for_label(label.as_bytes())
+            .ok_or_else(|| format!("unsupported charset {}", label))?,
+        None => encoding_rs::UTF_8,
+    };
+
+    if encoding == encoding_rs::UTF_8 {
+        return String::from_utf8(bytes).map_err(|_| String::from("invalid UTF-8"));
+    }
+    match encoding.decode_without_bom_handling_and_without_replacement(&bytes) {
+        Some(decoded) => Ok(decoded.into_owned()),
+        None => Err(format!("invalid {}", encoding.name())),
+    }
+}
+
+/// Decodes a content line that isn't valid UTF-8 with the charset given in
+/// its `CHARSET` parameter, as vCard 2.1 allows raw 8-bit values.
+pub fn decode_legacy_line(line: Vec<u8>) -> Option<String> {
+    let name_and_params_end = line.iter().position(|b| *b == b':')?;
+    let name_and_params = std::str::from_utf8(&line[..name_and_params_end]).ok()?;
+    let charset = name_and_params.split(';').skip(1).find_map(|param| {
+        param
+            .split_once('=')
+            .filter(|(name, _)| name.eq_ignore_ascii_case("CHARSET"))
+            .map(|(_, value)| value.to_string())
+    })?;
+    decode_charset(line, Some(&charset)).ok()
+}
+
+/// Escapes a text value (RFC 6350 section 3.4): `\\`, `,`, `;` and line
+/// breaks.
+pub fn escape_text(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.replace("\r\n", "\n").chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            ',' => escaped.push_str("\\,"),
+            ';' => escaped.push_str("\\;"),
+            '\n' => escaped.push_str("\\n"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Reverses [`escape_text`], also accepting `\N` for a line break. Unknown
+/// escapes like `\:` are kept as they are.
+pub fn unescape_text(text: &str) -> String {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n' | 'N') => unescaped.push('\n'),
+            Some(c @ ('\\' | ',' | ';')) => unescaped.push(c),
+            Some(other) => {
+                unescaped.push('\\');
+                unescaped.push(other);
+            }
+            None => unescaped.push('\\'),
+        }
+    }
+    unescaped
+}
+
+/// Splits an escaped value at each `separator` that isn't escaped, leaving
+/// the parts escaped.
+pub fn split_escaped(text: &str, separator: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut start = 0;
+    let mut escaped = false;
+    for (i, c) in text.char_indices() {
+        if escaped {
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if c == separator {
+            parts.push(&text[start..i]);
+            start = i + c.len_utf8();
+        }
+    }
+    parts.push(&text[start..]);
+    parts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quoted_printable() {
+        assert_eq!(
+            "Täst".as_bytes(),
+            decode_quoted_printable("=54=C3=A4=73=74").as_slice()
+        );
+        assert_eq!(
+            "Mixed Täst = 1".as_bytes(),
+            decode_quoted_printable("Mixed T=c3=A4st = 1").as_slice()
+        );
+        assert_eq!(
+            b"a\r\nb=".as_slice(),
+            decode_quoted_printable("a=0D=0Ab=").as_slice()
+        );
+    }
+
+    #[test]
+    fn base64() {
+        assert_eq!(Ok(b"Hello".to_vec()), decode_base64("SGVsbG8="));
+        assert_eq!(Ok(b"Hello!".to_vec()), decode_base64("SGVs\r\n bG8h"));
+        assert_eq!(Ok("Täst".as_bytes().to_vec()), decode_base64("VMOkc3Q"));
+        assert!(decode_base64("SGVs*bG8=").is_err());
+    }
+
+    #[test]
+    fn charsets() {
+        assert_eq!(
+            Ok(String::from("Täst")),
+            decode_charset(vec![b'T', 0xE4, b's', b't'], Some("ISO-8859-1"))
+        );
+        assert_eq!(
+            Ok(String::from("€ – Test")),
+            decode_charset(
+                vec![0x80, b' ', 0x96, b' ', b'T', b'e', b's', b't'],
+                Some("windows-1252")
+            )
+        );
+        assert_eq!(
+            Ok(String::from("Täst")),
+            decode_charset("Täst".as_bytes().to_vec(), None)
+        );
+        assert!(decode_charset(vec![b'T', 0xE4], Some("UTF-8")).is_err());
+        assert!(decode_charset(vec![b'T'], Some("X-UNKNOWN")).is_err());
+    }
+
+    #[test]
+    fn legacy_line() {
+        assert_eq!(
+            Some(String::from("FN;CHARSET=ISO-8859-1:Täst")),
+            decode_legacy_line(b"FN;CHARSET=ISO-8859-1:T\xE4st".to_vec())
+        );
+        assert_eq!(None, decode_legacy_line(b"FN:T\xE4st".to_vec()));
+    }
+
+    #[test]
+    fn split() {
+        assert_eq!(
+            vec![r"Smith\, Jr.", "John", "", ""],
+            split_escaped(r"Smith\, Jr.;John;;", ';')
+        );
+        assert_eq!(vec![r"a\\", r"b\,c"], split_escaped(r"a\\,b\,c", ','));
+        assert_eq!(vec![""], split_escaped("", ','));
+    }
+
+    #[test]
+    fn escaping() {
+        let text = "Smith, John; \\o/\nline 2";
+        assert_eq!("Smith\\, John\\; \\\\o/\\nline 2", escape_text(text));
+        assert_eq!(text, unescape_text(&escape_text(text)));
+        assert_eq!("a\\nb", escape_text("a\r\nb"));
+        assert_eq!("a\nb \\: c\\", unescape_text("a\\Nb \\: c\\"));
+    }
+}
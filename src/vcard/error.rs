@@ -0,0 +1,187 @@
+use std::{error, fmt};
+
+/// Snippets longer than this are cut off, e.g. for embedded photos.
+const MAX_SNIPPET_LENGTH: usize = 80;
+
+#[derive(Debug, PartialEq)]
+pub struct VCardError {
+    pub kind: ErrorKind,
+    /// Line of the offending content line, starting at 1. For folded lines
+    /// this is the first physical line.
+    pub line: Option<usize>,
+    /// Column within the unfolded content line, in characters starting at 1.
+    pub column: Option<usize>,
+    /// The offending content line, unfolded.
+    pub snippet: Option<String>,
+    /// `FN` or `UID` of the card the error occurred in, if known.
+    pub card: Option<String>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ErrorKind {
+    UnexpectedFieldError(String),
+    MissingEndError,
+    NoNameError,
+    InvalidBDayError(String),
+    InvalidDateError(String),
+    InvalidPropertyError(String),
+    InvalidEncodingError(String),
+    InvalidUtf8Error,
+    IoError(String),
+    InvalidJCardError(String),
+    InvalidXCardError(String),
+    InvalidCsvError(String),
+    InvalidLdifError(String),
+}
+
+impl VCardError {
+    pub(crate) fn at(mut self, line: usize, snippet: &str) -> Self {
+        self.line = Some(line);
+        self.snippet = Some(String::from(snippet));
+        self
+    }
+
+    pub(crate) fn with_column(mut self, column: usize) -> Self {
+        self.column = Some(column);
+        self
+    }
+
+    pub(crate) fn in_card(mut self, card: Option<String>) -> Self {
+        self.card = card;
+        self
+    }
+}
+
+impl From<ErrorKind> for VCardError {
+    fn from(kind: ErrorKind) -> Self {
+        VCardError {
+            kind,
+            line: None,
+            column: None,
+            snippet: None,
+            card: None,
+        }
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        match self {
+            Self::UnexpectedFieldError(field) => write!(f, "Unexpected {}", field),
+            Self::MissingEndError => write!(f, "Missing END:VCARD"),
+            Self::NoNameError => write!(f, "No name at end of vcard"),
+            Self::InvalidBDayError(msg) => write!(f, "error while parsing bday: {}", msg),
+            Self::InvalidDateError(msg) => write!(f, "error while parsing date: {}", msg),
+            Self::InvalidPropertyError(msg) => write!(f, "invalid content line: {}", msg),
+            Self::InvalidEncodingError(msg) => write!(f, "error while decoding value: {}", msg),
+            Self::InvalidUtf8Error => write!(f, "line is not valid UTF-8"),
+            Self::IoError(msg) => write!(f, "error while reading: {}", msg),
+            Self::InvalidJCardError(msg) => write!(f, "invalid jCard: {}", msg),
+            Self::InvalidXCardError(msg) => write!(f, "invalid xCard: {}", msg),
+            Self::InvalidCsvError(msg) => write!(f, "invalid CSV: {}", msg),
+            Self::InvalidLdifError(msg) => write!(f, "invalid LDIF: {}", msg),
+        }
+    }
+}
+
+/// Formats the error like a compiler diagnostic:
+///
+/// ```text
+/// error while parsing bday: input contains invalid characters
+///  --> line 12, column 6 (vcard "Bob Test")
+///    |
+/// 12 | BDAY:1980-asdf-07
+///    |      ^
+/// ```
+impl fmt::Display for VCardError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        write!(f, "{}", self.kind)?;
+
+        let Some(line) = self.line else {
+            if let Some(card) = &self.card {
+                write!(f, " (vcard \"{}\")", card)?;
+            }
+            return Ok(());
+        };
+
+        let gutter = " ".repeat(line.to_string().len());
+        write!(f, "\n{}--> line {}", gutter, line)?;
+        if let Some(column) = self.column {
+            write!(f, ", column {}", column)?;
+        }
+        if let Some(card) = &self.card {
+            write!(f, " (vcard \"{}\")", card)?;
+        }
+
+        if let Some(snippet) = &self.snippet {
+            let mut shortened: String = snippet.chars().take(MAX_SNIPPET_LENGTH).collect();
+            if shortened.len() < snippet.len() {
+                shortened.push_str("...");
+            }
+            write!(f, "\n{} |\n{} | {}", gutter, line, shortened)?;
+            if let Some(column) = self.column.filter(|c| (1..=MAX_SNIPPET_LENGTH).contains(c)) {
+                write!(f, "\n{} | {}^", gutter, " ".repeat(column - 1))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl error::Error for VCardError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn display_without_location() {
+        let error = VCardError::from(ErrorKind::NoNameError);
+        assert_eq!("No name at end of vcard", error.to_string());
+
+        let error = error.in_card(Some(String::from("urn:uuid:1234")));
+        assert_eq!(
+            "No name at end of vcard (vcard \"urn:uuid:1234\")",
+            error.to_string()
+        );
+    }
+
+    #[test]
+    fn display_with_location() {
+        let error = VCardError::from(ErrorKind::InvalidBDayError(String::from(
+            "input contains invalid characters",
+        )))
+        .at(12, "BDAY:1980-asdf-07")
+        .with_column(6)
+        .in_card(Some(String::from("Bob Test")));
+        assert_eq!(
+            "\
+error while parsing bday: input contains invalid characters
+  --> line 12, column 6 (vcard \"Bob Test\")
+   |
+12 | BDAY:1980-asdf-07
+   |      ^",
+            error.to_string()
+        );
+    }
+
+    #[test]
+    fn display_long_snippet() {
+        let snippet = format!("PHOTO;ENCODING=b:{}", "A".repeat(100));
+        let error = VCardError::from(ErrorKind::InvalidEncodingError(String::from(
+            "invalid base64 character '*'",
+        )))
+        .at(3, &snippet)
+        .with_column(90);
+        assert_eq!(
+            format!(
+                "\
+error while decoding value: invalid base64 character '*'
+ --> line 3, column 90
+  |
+3 | PHOTO;ENCODING=b:{}...",
+                "A".repeat(63)
+            ),
+            error.to_string()
+        );
+    }
+}
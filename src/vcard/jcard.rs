@@ -0,0 +1,273 @@
+use super::encoding::escape_text;
+use super::{ErrorKind, Param, ParsedVCards, Property, VCard, VCardError};
+use serde_json::Value;
+
+/// Parses all jCards (RFC 7095) in `contents`, failing at the first error.
+/// `contents` is either a single jCard or an array of them.
+pub fn parse_jcards(contents: impl AsRef<[u8]>) -> Result<Vec<VCard>, VCardError> {
+    read_jcards(contents.as_ref()).into_iter().collect()
+}
+
+/// Parses all jCards in `contents`, skipping broken cards instead of
+/// failing.
+pub fn parse_jcards_lenient(contents: impl AsRef<[u8]>) -> ParsedVCards {
+    read_jcards(contents.as_ref()).into_iter().collect()
+}
+
+fn read_jcards(contents: &[u8]) -> Vec<Result<VCard, VCardError>> {
+    let json: Value = match serde_json::from_slice(contents) {
+        Ok(json) => json,
+        Err(e) => return vec![Err(invalid(e.to_string()))],
+    };
+    if is_jcard(&json) {
+        return vec![read_jcard(&json)];
+    }
+    match json {
+        Value::Array(jcards) => jcards.iter().map(read_jcard).collect(),
+        _ => vec![Err(invalid("expected a jCard or an array of jCards"))],
+    }
+}
+
+fn is_jcard(json: &Value) -> bool {
+    json.get(0).and_then(Value::as_str) == Some("vcard")
+}
+
+/// Converts the jCard to the properties of a vCard, so that it's read just
+/// like one.
+fn read_jcard(jcard: &Value) -> Result<VCard, VCardError> {
+    let properties = match jcard.as_array().map(Vec::as_slice) {
+        Some([Value::String(vcard), Value::Array(properties)]) if vcard == "vcard" => properties,
+        _ => return Err(invalid("expected [\"vcard\", [properties...]]")),
+    };
+    let properties = properties
+        .iter()
+        .map(read_property)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    VCard::from_properties(&properties).map_err(|e| {
+        let card = properties
+            .iter()
+            .find(|property| ["FN", "UID"].contains(&property.name.as_str()))
+            .and_then(|property| property.text_value().ok());
+        e.in_card(card)
+    })
+}
+
+/// Reads a property like `["bday", {}, "date", "1980-05-07"]`.
+fn read_property(property: &Value) -> Result<Property, VCardError> {
+    let [Value::String(name), Value::Object(params), Value::String(value_type), values @ ..] =
+        property.as_array().map(Vec::as_slice).unwrap_or_default()
+    else {
+        return Err(invalid(format!(
+            "expected [name, parameters, type, values...], got {}",
+            property
+        )));
+    };
+    if values.is_empty() {
+        return Err(invalid(format!("no value in {}", property)));
+    }
+
+    let mut group = None;
+    let mut property_params = Vec::new();
+    for (param_name, param_values) in params {
+        let param_values = match param_values {
+            Value::Array(param_values) => param_values.iter().map(text).collect(),
+            param_value => vec![text(param_value)],
+        };
+        if param_name == "group" {
+            group = param_values.into_iter().next();
+        } else {
+            property_params.push(Param {
+                name: param_name.to_ascii_uppercase(),
+                values: param_values,
+            });
+        }
+    }
+    if value_type != "unknown" {
+        property_params.push(Param {
+            name: String::from("VALUE"),
+            values: vec![value_type.clone()],
+        });
+    }
+
+    // multiple values (e.g. of NICKNAME) are separated by ',', the components
+    // of structured values (e.g. of N) by ';', like in a vCard
+    let value = values
+        .iter()
+        .map(|value| match value {
+            Value::Array(components) => components
+                .iter()
+                .map(|component| match component {
+                    Value::Array(values) => values
+                        .iter()
+                        .map(|value| escape_text(&text(value)))
+                        .collect::<Vec<_>>()
+                        .join(","),
+                    component => escape_text(&text(component)),
+                })
+                .collect::<Vec<_>>()
+                .join(";"),
+            value => escape_text(&text(value)),
+        })
+        .collect::<Vec<_>>()
+        .join(",");
+
+    Ok(Property {
+        group,
+        name: name.to_ascii_uppercase(),
+        params: property_params,
+        value,
+    })
+}
+
+/// A string, number or boolean value as text.
+fn text(value: &Value) -> String {
+    match value {
+        Value::String(text) => text.clone(),
+        value => value.to_string(),
+    }
+}
+
+fn invalid(msg: impl Into<String>) -> VCardError {
+    ErrorKind::InvalidJCardError(msg.into()).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vcard::{parse_vcards, DateAndOrTime, Event, EventKind, StructuredName};
+    use chrono::NaiveDate;
+
+    #[test]
+    fn parse_jcard() {
+        let input = r#"
+["vcard",
+  [
+    ["version", {}, "text", "4.0"],
+    ["fn", {}, "text", "Smith, John"],
+    ["n", {}, "text", ["Smith", "John", ["Alexander", "Al"], "", ""]],
+    ["uid", {}, "uri", "urn:uuid:1234"],
+    ["bday", {}, "date-and-or-time", "--05-07"],
+    ["anniversary", {}, "date", "2010-08-01"],
+    ["x-abdate", {"group": "item1"}, "date", "2012-06-24"],
+    ["x-ablabel", {"group": "item1"}, "text", "name day"]
+  ]
+]"#;
+
+        let result = parse_jcards(input).unwrap();
+
+        let date = |year, month, day| {
+            DateAndOrTime::Date(NaiveDate::from_ymd_opt(year, month, day).unwrap())
+        };
+        assert_eq!(
+            vec![VCard {
+                name: String::from("Smith, John"),
+                structured_name: Some(StructuredName {
+                    family: vec![String::from("Smith")],
+                    given: vec![String::from("John")],
+                    additional: vec![String::from("Alexander"), String::from("Al")],
+                    ..Default::default()
+                }),
+                uid: Some(String::from("urn:uuid:1234")),
+                bday: Some(DateAndOrTime::Partial {
+                    year: None,
+                    month: Some(5),
+                    day: Some(7)
+                }),
+                events: vec![
+                    Event {
+                        kind: EventKind::Anniversary,
+                        date: date(2010, 8, 1),
+                    },
+                    Event {
+                        kind: EventKind::Custom(String::from("name day")),
+                        date: date(2012, 6, 24),
+                    },
+                ],
+            }],
+            result
+        );
+    }
+
+    #[test]
+    fn parse_jcards_same_as_vcards() {
+        let jcards = r#"[
+  ["vcard", [
+    ["version", {}, "text", "4.0"],
+    ["fn", {}, "text", "Allice Test"],
+    ["bday", {}, "date", "1980-05-07"]
+  ]],
+  ["vcard", [
+    ["version", {}, "text", "4.0"],
+    ["n", {}, "text", ["Test", "Bob", "", "", ""]],
+    ["nickname", {}, "text", "Bobby", "B"],
+    ["bday", {}, "text", "circa 1800"]
+  ]]
+]"#;
+        let vcards = "\
+BEGIN:VCARD
+VERSION:4.0
+FN:Allice Test
+BDAY:1980-05-07
+END:VCARD
+BEGIN:VCARD
+VERSION:4.0
+N:Test;Bob;;;
+NICKNAME:Bobby,B
+BDAY;VALUE=text:circa 1800
+END:VCARD
+";
+
+        assert_eq!(parse_vcards(vcards).unwrap(), parse_jcards(jcards).unwrap());
+    }
+
+    #[test]
+    fn parse_jcards_lenient_skips_broken_cards() {
+        let input = r#"[
+  ["vcard", [["fn", {}, "text", "Allice Test"]]],
+  ["vcard", [["fn", {}, "text", "Bob Test"], ["bday", {}, "date", "1980-asdf-07"]]],
+  ["vcard", [["fn", {}, "text"]]],
+  ["vcard", [["note", {}, "text", "no name"]]]
+]"#;
+
+        let result = parse_jcards_lenient(input);
+
+        assert_eq!(
+            vec![VCard {
+                name: String::from("Allice Test"),
+                ..Default::default()
+            }],
+            result.vcards
+        );
+        assert_eq!(
+            vec![
+                (
+                    ErrorKind::InvalidBDayError(String::from("input contains invalid characters")),
+                    Some(String::from("Bob Test"))
+                ),
+                (
+                    ErrorKind::InvalidJCardError(String::from("no value in [\"fn\",{},\"text\"]")),
+                    None
+                ),
+                (ErrorKind::NoNameError, None),
+            ],
+            result
+                .diagnostics
+                .into_iter()
+                .map(|d| (d.kind, d.card))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn parse_jcards_invalid_json() {
+        let result = parse_jcards("BEGIN:VCARD").unwrap_err();
+        assert!(matches!(result.kind, ErrorKind::InvalidJCardError(_)));
+
+        let result = parse_jcards(r#"{"vcard": []}"#).unwrap_err();
+        assert_eq!(
+            ErrorKind::InvalidJCardError(String::from("expected a jCard or an array of jCards")),
+            result.kind
+        );
+    }
+}
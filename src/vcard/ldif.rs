@@ -0,0 +1,326 @@
+use super::encoding::decode_base64;
+use super::{ErrorKind, ParsedVCards, Unfold, VCard, VCardError};
+use crate::fields::{contact_properties, thunderbird_birthday};
+
+/// Parses the entries of an LDIF file (RFC 2849), e.g. exported by
+/// Thunderbird or an LDAP directory, skipping broken entries.
+///
+/// Birthdays are read from Thunderbird's `birthyear`, `birthmonth` and
+/// `birthday` attributes, or from `birthDate` or `dateOfBirth` (in
+/// `YYYY-MM-DD` or `YYYYMMDD` form) of common LDAP schemas. Entries that
+/// aren't people, e.g. mailing lists, are ignored.
+pub fn parse_ldif(contents: impl AsRef<[u8]>) -> ParsedVCards {
+    let mut parsed = ParsedVCards::default();
+    let mut entry: Option<Entry> = None;
+
+    for line in Unfold::new(contents.as_ref()) {
+        let (line_number, line) = match line {
+            Ok(line) => line,
+            Err(e) => {
+                parsed
+                    .diagnostics
+                    .push(ErrorKind::IoError(e.to_string()).into());
+                break;
+            }
+        };
+        if line.is_empty() {
+            // entries are separated by empty lines
+            if let Some(entry) = entry.take() {
+                finish_entry(entry, &mut parsed);
+            }
+            continue;
+        }
+        if line.starts_with(b"#") {
+            continue;
+        }
+
+        let entry = entry.get_or_insert_with(|| Entry {
+            line: line_number,
+            ..Default::default()
+        });
+        match read_attribute(&line) {
+            Ok(Some(attribute)) => entry.attributes.push(attribute),
+            Ok(None) => (),
+            Err(e) if !entry.broken => {
+                let snippet = String::from_utf8_lossy(&line).into_owned();
+                parsed.diagnostics.push(e.at(line_number, &snippet));
+                entry.broken = true;
+            }
+            Err(_) => (),
+        }
+    }
+    if let Some(entry) = entry {
+        finish_entry(entry, &mut parsed);
+    }
+
+    parsed
+}
+
+fn finish_entry(entry: Entry, parsed: &mut ParsedVCards) {
+    if entry.broken {
+        return;
+    }
+    match read_entry(&entry) {
+        Ok(Some(vcard)) => parsed.vcards.push(vcard),
+        Ok(None) => (),
+        Err(e) => {
+            let dn = entry.value("dn").unwrap_or_default();
+            parsed
+                .diagnostics
+                .push(e.at(entry.line, &format!("dn: {}", dn)));
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Entry {
+    /// Line number of the first attribute.
+    line: usize,
+    /// Names in lower case without options (e.g. `cn;lang-de`).
+    attributes: Vec<(String, String)>,
+    /// Whether an attribute couldn't be read, which was reported already.
+    broken: bool,
+}
+
+impl Entry {
+    fn value(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(n, value)| n == name && !value.trim().is_empty())
+            .map(|(_, value)| value.trim())
+    }
+
+    fn values<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a str> {
+        self.attributes
+            .iter()
+            .filter(move |(n, _)| n == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Reads `name: value` or `name:: base64`, `None` for values given by URL
+/// (`name:< url`) and for the `version` of the file.
+fn read_attribute(line: &[u8]) -> Result<Option<(String, String)>, VCardError> {
+    let invalid = |msg: &str| VCardError::from(ErrorKind::InvalidLdifError(String::from(msg)));
+    let line =
+        std::str::from_utf8(line).map_err(|_| VCardError::from(ErrorKind::InvalidUtf8Error))?;
+    let (name, value) = line.split_once(':').ok_or_else(|| invalid("missing ':'"))?;
+    let name = name
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    if name.is_empty() {
+        return Err(invalid("missing attribute name"));
+    }
+
+    let value = if let Some(encoded) = value.strip_prefix(':') {
+        let decoded = decode_base64(encoded).map_err(|e| invalid(&e))?;
+        String::from_utf8(decoded).map_err(|_| VCardError::from(ErrorKind::InvalidUtf8Error))?
+    } else if value.starts_with('<') {
+        return Ok(None);
+    } else {
+        String::from(value.trim_start())
+    };
+    Ok(if name == "version" {
+        None
+    } else {
+        Some((name, value))
+    })
+}
+
+/// Converts the entry to the properties of a vCard, so that it's read just
+/// like one. `None` for entries that aren't people.
+fn read_entry(entry: &Entry) -> Result<Option<VCard>, VCardError> {
+    let is_group = entry.values("objectclass").any(|class| {
+        ["groupofnames", "groupofuniquenames", "organizationalunit"]
+            .contains(&class.trim().to_ascii_lowercase().as_str())
+    });
+    if is_group || entry.values("changetype").any(|t| t.trim() == "delete") {
+        return Ok(None);
+    }
+
+    let properties = contact_properties(
+        entry.value("displayname").or_else(|| entry.value("cn")),
+        entry.value("givenname"),
+        entry.value("sn"),
+        entry.value("o"),
+        entry.value("mozillanickname"),
+        birthday(entry),
+    );
+    VCard::from_properties(&properties)
+        .map(Some)
+        .map_err(|e| e.in_card(entry.value("dn").map(String::from)))
+}
+
+/// The birthday as vCard value, e.g. `1980-05-07` or `--05-07` if the year
+/// isn't known.
+fn birthday(entry: &Entry) -> Option<String> {
+    if let Some(date) = entry
+        .value("birthdate")
+        .or_else(|| entry.value("dateofbirth"))
+        .or_else(|| entry.value("schacdateofbirth"))
+    {
+        return Some(String::from(date));
+    }
+
+    thunderbird_birthday(
+        entry.value("birthyear"),
+        entry.value("birthmonth"),
+        entry.value("birthday"),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vcard::{DateAndOrTime, StructuredName};
+    use chrono::NaiveDate;
+
+    #[test]
+    fn parse_thunderbird_ldif() {
+        let input = "\
+dn: cn=Allice Test,mail=allice@example.com
+objectclass: top
+objectclass: person
+objectclass: mozillaAbPersonAlpha
+givenName: Allice
+sn: Test
+cn: Allice Test
+mail: allice@example.com
+birthyear: 1980
+birthmonth: 5
+birthday: 07
+
+dn:: Y249SsO8cmdlbiBUZXN0
+objectclass: person
+cn:: SsO8cmdlbiBUZXN0
+mozillaNickname: J
+birthmonth: 06
+birthday: 08
+description: a long description that goes on
+  and on
+
+dn: cn=Friends
+objectclass: top
+objectclass: groupOfNames
+cn: Friends
+member: cn=Allice Test,mail=allice@example.com
+";
+
+        let parsed = parse_ldif(input);
+
+        assert!(parsed.diagnostics.is_empty(), "{:?}", parsed.diagnostics);
+        assert_eq!(
+            vec![
+                VCard {
+                    name: String::from("Allice Test"),
+                    structured_name: Some(StructuredName {
+                        family: vec![String::from("Test")],
+                        given: vec![String::from("Allice")],
+                        ..Default::default()
+                    }),
+                    bday: NaiveDate::from_ymd_opt(1980, 5, 7).map(DateAndOrTime::Date),
+                    ..Default::default()
+                },
+                VCard {
+                    name: String::from("J\u{fc}rgen Test"),
+                    bday: Some(DateAndOrTime::Partial {
+                        year: None,
+                        month: Some(6),
+                        day: Some(8)
+                    }),
+                    ..Default::default()
+                },
+            ],
+            parsed.vcards
+        );
+    }
+
+    #[test]
+    fn parse_ldap_schemas() {
+        let input = "\
+version: 1
+# exported from the directory
+dn: uid=bob,ou=people,dc=example,dc=com
+objectClass: inetOrgPerson
+displayName;lang-en: Bob Test
+cn: Robert Test
+birthDate: 1981-06-08
+
+dn: uid=carl,ou=people,dc=example,dc=com
+objectClass: inetOrgPerson
+cn: Carl Test
+schacDateOfBirth: 19820709
+";
+
+        let parsed = parse_ldif(input);
+
+        assert!(parsed.diagnostics.is_empty(), "{:?}", parsed.diagnostics);
+        let bdays: Vec<_> = parsed
+            .vcards
+            .iter()
+            .map(|vcard| (vcard.name.as_str(), vcard.bday.clone()))
+            .collect();
+        assert_eq!(
+            vec![
+                (
+                    "Bob Test",
+                    NaiveDate::from_ymd_opt(1981, 6, 8).map(DateAndOrTime::Date)
+                ),
+                (
+                    "Carl Test",
+                    NaiveDate::from_ymd_opt(1982, 7, 9).map(DateAndOrTime::Date)
+                ),
+            ],
+            bdays
+        );
+    }
+
+    #[test]
+    fn parse_ldif_skips_broken_entries() {
+        let input = "\
+dn: cn=Allice Test
+cn: Allice Test
+birthmonth: 13
+birthday: 7
+
+dn: cn=Bob Test
+cn:: ***
+birthDate: 1981-06-08
+
+dn: cn=Carl Test
+cn: Carl Test
+";
+
+        let parsed = parse_ldif(input);
+
+        assert_eq!(
+            vec!["Carl Test"],
+            parsed
+                .vcards
+                .iter()
+                .map(|vcard| vcard.name.as_str())
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            vec![
+                (Some(1), Some(String::from("cn=Allice Test"))),
+                (Some(7), None)
+            ],
+            parsed
+                .diagnostics
+                .iter()
+                .map(|e| (e.line, e.card.clone()))
+                .collect::<Vec<_>>()
+        );
+        assert!(matches!(
+            parsed.diagnostics[0].kind,
+            ErrorKind::InvalidBDayError(_)
+        ));
+        assert!(matches!(
+            parsed.diagnostics[1].kind,
+            ErrorKind::InvalidLdifError(_)
+        ));
+    }
+}
@@ -0,0 +1,336 @@
+use super::encoding;
+use super::{ErrorKind, VCardError};
+
+/// A single content line of a vCard (RFC 6350 section 3.3), e.g.
+/// `item1.TEL;TYPE=work,voice:+01234567890`.
+///
+/// Names of groups, properties and parameters are case-insensitive, property
+/// and parameter names are stored in upper case.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Property {
+    pub group: Option<String>,
+    pub name: String,
+    pub params: Vec<Param>,
+    /// The raw value, still encoded and escaped as in the content line.
+    pub value: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Param {
+    pub name: String,
+    pub values: Vec<String>,
+}
+
+impl Property {
+    /// Tokenizes an unfolded content line.
+    pub fn parse(line: &str) -> Result<Property, VCardError> {
+        // `rest` is the remainder of the line where the error was found
+        let invalid = |reason: &str, rest: &str| {
+            let column = line[..line.len() - rest.len()].chars().count() + 1;
+            VCardError::from(ErrorKind::InvalidPropertyError(format!(
+                "{}: {}",
+                reason, line
+            )))
+            .with_column(column)
+        };
+
+        let name_end = line
+            .find([';', ':'])
+            .ok_or_else(|| invalid("missing ':'", ""))?;
+        let (group, name) = match line[..name_end].split_once('.') {
+            Some((group, name)) => (Some(group), name),
+            None => (None, &line[..name_end]),
+        };
+        if !group.is_none_or(is_valid_name) || !is_valid_name(name) {
+            return Err(invalid("invalid property name", line));
+        }
+
+        let mut params = Vec::new();
+        let mut rest = &line[name_end..];
+        while let Some(param) = rest.strip_prefix(';') {
+            let param_name_end = param
+                .find(['=', ';', ':'])
+                .ok_or_else(|| invalid("missing ':'", ""))?;
+            let param_name = &param[..param_name_end];
+            if !is_valid_name(param_name) {
+                return Err(invalid("invalid parameter name", param));
+            }
+
+            rest = &param[param_name_end..];
+            let Some(mut param_values) = rest.strip_prefix('=') else {
+                params.push(bare_param(param_name));
+                continue;
+            };
+
+            let mut values = Vec::new();
+            loop {
+                if let Some(quoted) = param_values.strip_prefix('"') {
+                    let end = quoted.find('"').ok_or_else(|| {
+                        invalid("unterminated quoted parameter value", param_values)
+                    })?;
+                    values.push(decode_param_value(&quoted[..end]));
+                    param_values = &quoted[end + 1..];
+                } else {
+                    let end = param_values
+                        .find([',', ';', ':'])
+                        .ok_or_else(|| invalid("missing ':'", ""))?;
+                    values.push(decode_param_value(&param_values[..end]));
+                    param_values = &param_values[end..];
+                }
+                match param_values.strip_prefix(',') {
+                    Some(next) => param_values = next,
+                    None => break,
+                }
+            }
+            params.push(Param {
+                name: param_name.to_ascii_uppercase(),
+                values,
+            });
+            rest = param_values;
+        }
+
+        let value = rest
+            .strip_prefix(':')
+            .ok_or_else(|| invalid("missing ':'", rest))?;
+
+        Ok(Property {
+            group: group.map(String::from),
+            name: name.to_ascii_uppercase(),
+            params,
+            value: String::from(value),
+        })
+    }
+
+    pub fn param(&self, name: &str) -> Option<&Param> {
+        self.params
+            .iter()
+            .find(|param| param.name.eq_ignore_ascii_case(name))
+    }
+
+    /// The first value of the parameter `name`.
+    pub fn param_value(&self, name: &str) -> Option<&str> {
+        self.param(name)
+            .and_then(|param| param.values.first())
+            .map(String::as_str)
+    }
+
+    /// The value with its transfer encoding (`ENCODING=QUOTED-PRINTABLE`,
+    /// `ENCODING=BASE64` or `ENCODING=b`) removed.
+    pub fn decoded_value(&self) -> Result<Vec<u8>, VCardError> {
+        if self.is_quoted_printable() {
+            Ok(encoding::decode_quoted_printable(&self.value))
+        } else if self.is_base64() {
+            encoding::decode_base64(&self.value)
+                .map_err(|e| ErrorKind::InvalidEncodingError(e).into())
+        } else {
+            Ok(self.value.clone().into_bytes())
+        }
+    }
+
+    /// The value of a text property, decoded, converted from the charset
+    /// given in the `CHARSET` parameter and unescaped.
+    pub fn text_value(&self) -> Result<String, VCardError> {
+        Ok(encoding::unescape_text(&self.decoded_text()?))
+    }
+
+    /// The value of a structured property like `N` or `ORG`, split into its
+    /// `;`-separated components and their `,`-separated values.
+    pub fn structured_value(&self) -> Result<Vec<Vec<String>>, VCardError> {
+        Ok(encoding::split_escaped(&self.decoded_text()?, ';')
+            .into_iter()
+            .map(|component| {
+                encoding::split_escaped(component, ',')
+                    .into_iter()
+                    .map(encoding::unescape_text)
+                    .collect()
+            })
+            .collect())
+    }
+
+    /// The value decoded and converted from its charset, but still escaped.
+    fn decoded_text(&self) -> Result<String, VCardError> {
+        if self.is_quoted_printable() || self.is_base64() {
+            encoding::decode_charset(self.decoded_value()?, self.param_value("CHARSET"))
+                .map_err(|e| ErrorKind::InvalidEncodingError(e).into())
+        } else {
+            // raw 8-bit values were converted when reading the line
+            Ok(self.value.clone())
+        }
+    }
+
+    pub fn is_quoted_printable(&self) -> bool {
+        self.has_param_value("ENCODING", "QUOTED-PRINTABLE")
+    }
+
+    pub fn is_base64(&self) -> bool {
+        self.has_param_value("ENCODING", "BASE64") || self.has_param_value("ENCODING", "b")
+    }
+
+    /// Whether the parameter `name` has the value `value`, ignoring case.
+    pub fn has_param_value(&self, name: &str, value: &str) -> bool {
+        self.params
+            .iter()
+            .filter(|param| param.name.eq_ignore_ascii_case(name))
+            .flat_map(|param| &param.values)
+            .any(|v| v.eq_ignore_ascii_case(value))
+    }
+}
+
+fn is_valid_name(name: &str) -> bool {
+    // '_' isn't allowed by RFC 6350, but used by some exporters
+    !name.is_empty()
+        && name
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_')
+}
+
+/// vCard 2.1 allows parameters without a name, e.g. `TEL;CELL:` or
+/// `NOTE;QUOTED-PRINTABLE:`.
+fn bare_param(value: &str) -> Param {
+    let name = match value.to_ascii_uppercase().as_str() {
+        "7BIT" | "8BIT" | "QUOTED-PRINTABLE" | "BASE64" | "B" => "ENCODING",
+        _ => "TYPE",
+    };
+    Param {
+        name: String::from(name),
+        values: vec![String::from(value)],
+    }
+}
+
+/// Decodes the circumflex escapes of RFC 6868 (`^n`, `^^` and `^'`).
+fn decode_param_value(value: &str) -> String {
+    let mut decoded = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '^' {
+            decoded.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => decoded.push('\n'),
+            Some('^') => decoded.push('^'),
+            Some('\'') => decoded.push('"'),
+            Some(other) => {
+                decoded.push('^');
+                decoded.push(other);
+            }
+            None => decoded.push('^'),
+        }
+    }
+    decoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn param(name: &str, values: &[&str]) -> Param {
+        Param {
+            name: String::from(name),
+            values: values.iter().map(|v| String::from(*v)).collect(),
+        }
+    }
+
+    #[test]
+    fn parse_simple() {
+        assert_eq!(
+            Property {
+                group: None,
+                name: String::from("FN"),
+                params: vec![],
+                value: String::from("Bob Test"),
+            },
+            Property::parse("fn:Bob Test").unwrap()
+        );
+    }
+
+    #[test]
+    fn parse_group_and_params() {
+        let property = Property::parse(
+            "item1.tel;type=work,VOICE;PREF=1;LABEL=\"Main: Office; ^'2nd^' floor\":+0123:45",
+        )
+        .unwrap();
+        assert_eq!(
+            Property {
+                group: Some(String::from("item1")),
+                name: String::from("TEL"),
+                params: vec![
+                    param("TYPE", &["work", "VOICE"]),
+                    param("PREF", &["1"]),
+                    param("LABEL", &["Main: Office; \"2nd\" floor"]),
+                ],
+                value: String::from("+0123:45"),
+            },
+            property
+        );
+        assert_eq!(Some("work"), property.param_value("type"));
+        assert!(property.has_param_value("TYPE", "voice"));
+        assert!(!property.has_param_value("TYPE", "cell"));
+    }
+
+    #[test]
+    fn parse_bare_params() {
+        let property = Property::parse("TEL;CELL;quoted-printable:=30").unwrap();
+        assert_eq!(
+            vec![
+                param("TYPE", &["CELL"]),
+                param("ENCODING", &["quoted-printable"])
+            ],
+            property.params
+        );
+    }
+
+    #[test]
+    fn parse_empty_value() {
+        let property = Property::parse("NOTE;LANGUAGE=en:").unwrap();
+        assert_eq!("", property.value);
+    }
+
+    #[test]
+    fn structured_value() {
+        let property = Property::parse("N:Test;Bob;Alexander,Bobby;;Jr.").unwrap();
+        assert_eq!(
+            vec![
+                vec!["Test"],
+                vec!["Bob"],
+                vec!["Alexander", "Bobby"],
+                vec![""],
+                vec!["Jr."]
+            ],
+            property.structured_value().unwrap()
+        );
+    }
+
+    #[test]
+    fn structured_value_escaped() {
+        let property =
+            Property::parse(r"ADR;TYPE=work:;Floor 2\, Room 3;Main St. 1\;2;Town\\;12345;")
+                .unwrap();
+        assert_eq!(
+            vec![
+                vec![""],
+                vec!["Floor 2, Room 3"],
+                vec!["Main St. 1;2"],
+                vec!["Town\\"],
+                vec!["12345"],
+                vec![""]
+            ],
+            property.structured_value().unwrap()
+        );
+    }
+
+    #[test]
+    fn parse_invalid() {
+        for line in [
+            "no colon",
+            ":value",
+            "FN;:value",
+            "FN;X-PARAM=\"unterminated:value",
+            "FN;X-PARAM=no-colon",
+            "a.b.FN:value",
+            "F N:value",
+        ] {
+            assert!(Property::parse(line).is_err(), "{}", line);
+        }
+    }
+}
@@ -0,0 +1,210 @@
+use super::{ErrorKind, Parser, VCard, VCardError};
+use std::io::BufRead;
+
+/// Reads vCards one at a time, so only the current card (and not the whole
+/// file) is held in memory.
+///
+/// Errors of broken cards are returned in between the cards, the reader
+/// continues with the next card. After an I/O error it stops.
+pub struct VCardReader<R> {
+    lines: Unfold<R>,
+    parser: Parser,
+    finished: bool,
+}
+
+impl<R: BufRead> VCardReader<R> {
+    pub fn new(reader: R) -> Self {
+        VCardReader {
+            lines: Unfold::new(reader),
+            parser: Parser::new(),
+            finished: false,
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for VCardReader<R> {
+    type Item = Result<VCard, VCardError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
+        }
+
+        for line in self.lines.by_ref() {
+            let result = match line {
+                Ok((line_number, line)) => self.parser.parse_line(line_number, line),
+                Err(e) => {
+                    self.finished = true;
+                    Err(ErrorKind::IoError(e.to_string()).into())
+                }
+            };
+            if let Some(result) = result.transpose() {
+                return Some(result);
+            }
+        }
+
+        self.finished = true;
+        self.parser.finish().err().map(Err)
+    }
+}
+
+/// Splits the input into logical lines, joining folded lines (a line break
+/// followed by a single space or tab, see RFC 6350 section 3.2). Each line
+/// comes with the number of its first physical line. iCalendar uses the same
+/// folding (RFC 5545 section 3.1).
+///
+/// Unfolding happens on bytes, as exporters fold after a fixed number of
+/// octets and may split a multi-byte UTF-8 sequence across two lines.
+pub(crate) struct Unfold<R> {
+    reader: R,
+    /// The logical line read so far, it's complete once the next physical
+    /// line isn't a continuation.
+    pending: Option<(usize, Vec<u8>)>,
+    line_number: usize,
+}
+
+impl<R: BufRead> Unfold<R> {
+    pub(crate) fn new(reader: R) -> Self {
+        Unfold {
+            reader,
+            pending: None,
+            line_number: 0,
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for Unfold<R> {
+    type Item = std::io::Result<(usize, Vec<u8>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let mut line = Vec::new();
+            match self.reader.read_until(b'\n', &mut line) {
+                Ok(0) => return self.pending.take().map(Ok),
+                Ok(_) => (),
+                Err(e) => return Some(Err(e)),
+            }
+            self.line_number += 1;
+            if line.ends_with(b"\n") {
+                line.pop();
+            }
+            if line.ends_with(b"\r") {
+                line.pop();
+            }
+
+            match (line.first(), &mut self.pending) {
+                (Some(b' ' | b'\t'), Some((_, pending))) => pending.extend_from_slice(&line[1..]),
+                _ => {
+                    let previous = self.pending.replace((self.line_number, line));
+                    if previous.is_some() {
+                        return previous.map(Ok);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{self, BufReader, Read};
+
+    fn unfold(input: &[u8]) -> Vec<(usize, Vec<u8>)> {
+        Unfold::new(input).collect::<io::Result<_>>().unwrap()
+    }
+
+    #[test]
+    fn unfold_lines() {
+        assert_eq!(
+            vec![
+                (1, b"A:folded line".to_vec()),
+                (3, b"B:".to_vec()),
+                (4, b"".to_vec()),
+                (5, b"C:tab".to_vec()),
+            ],
+            unfold(b"A:folded \r\n line\r\nB:\n\nC:\n\ttab\n")
+        );
+        assert_eq!(
+            vec![(1, b"A:no line break".to_vec())],
+            unfold(b"A:no line break")
+        );
+        assert_eq!(Vec::<(usize, Vec<u8>)>::new(), unfold(b""));
+    }
+
+    #[test]
+    fn unfold_across_buffer_boundaries() {
+        let input: &[u8] = b"NOTE:a long\r\n  folded\r\n  line\r\nFN:T\xC3\r\n \xA4st\r\n";
+        let lines: Vec<_> = Unfold::new(BufReader::with_capacity(3, input))
+            .collect::<io::Result<_>>()
+            .unwrap();
+        assert_eq!(
+            vec![
+                (1, b"NOTE:a long folded line".to_vec()),
+                (4, "FN:Täst".as_bytes().to_vec()),
+            ],
+            lines
+        );
+    }
+
+    /// Fails after returning `data`.
+    struct FailingRead<'a> {
+        data: &'a [u8],
+    }
+
+    impl Read for FailingRead<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.data.is_empty() {
+                return Err(io::Error::other("disk on fire"));
+            }
+            self.data.read(buf)
+        }
+    }
+
+    #[test]
+    fn reader_stops_after_io_error() {
+        let input = FailingRead {
+            data: b"BEGIN:VCARD\nFN:Allice Test\nEND:VCARD\nBEGIN:VCARD\nFN:Bob",
+        };
+        let mut reader = VCardReader::new(BufReader::new(input));
+
+        assert_eq!(
+            VCard {
+                name: String::from("Allice Test"),
+                ..Default::default()
+            },
+            reader.next().unwrap().unwrap()
+        );
+        assert_eq!(
+            ErrorKind::IoError(String::from("disk on fire")),
+            reader.next().unwrap().unwrap_err().kind
+        );
+        assert!(reader.next().is_none());
+    }
+
+    #[test]
+    fn reader_yields_cards_and_errors() {
+        let input = "\
+BEGIN:VCARD
+FN:Allice Test
+END:VCARD
+BEGIN:VCARD
+TEL:+01234567890
+END:VCARD
+BEGIN:VCARD
+FN:Charlie Test
+";
+        let results: Vec<_> = VCardReader::new(input.as_bytes()).collect();
+
+        assert_eq!(3, results.len());
+        assert!(results[0].is_ok());
+        assert_eq!(
+            ErrorKind::NoNameError,
+            results[1].as_ref().unwrap_err().kind
+        );
+        assert_eq!(
+            ErrorKind::MissingEndError,
+            results[2].as_ref().unwrap_err().kind
+        );
+    }
+}
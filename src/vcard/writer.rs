@@ -0,0 +1,403 @@
+use super::encoding::escape_text;
+use super::{DateAndOrTime, Event, EventKind, StructuredName, VCard};
+use std::io::{self, Write};
+
+/// Content lines are folded after this many octets (RFC 6350 section 3.2).
+const MAX_LINE_LENGTH: usize = 75;
+
+/// Placeholder year for dates without a year in vCard 3.0, which has no
+/// syntax for them. 1604 is a leap year, so February 29 works too.
+const OMITTED_YEAR: i32 = 1604;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Version {
+    /// vCard 3.0 (RFC 2426)
+    V3,
+    /// vCard 4.0 (RFC 6350)
+    V4,
+}
+
+/// Writes vCards in the given version, with escaped values and folded lines.
+///
+/// Dates without a year are written with Apple's `X-APPLE-OMIT-YEAR` in
+/// vCard 3.0, events other than anniversaries as labeled Apple dates
+/// (`X-ABDATE`), as understood by most address books. Text dates and
+/// partial dates without month and day can't be written in vCard 3.0 and
+/// are left out.
+pub struct VCardWriter<W> {
+    writer: W,
+    version: Version,
+}
+
+impl<W: Write> VCardWriter<W> {
+    pub fn new(writer: W, version: Version) -> Self {
+        VCardWriter { writer, version }
+    }
+
+    pub fn write(&mut self, vcard: &VCard) -> io::Result<()> {
+        for line in self.content_lines(vcard) {
+            self.writer.write_all(fold(&line).as_bytes())?;
+            self.writer.write_all(b"\r\n")?;
+        }
+        Ok(())
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    fn content_lines(&self, vcard: &VCard) -> Vec<String> {
+        let mut lines = vec![String::from("BEGIN:VCARD")];
+        lines.push(match self.version {
+            Version::V3 => String::from("VERSION:3.0"),
+            Version::V4 => String::from("VERSION:4.0"),
+        });
+        lines.push(format!("FN:{}", escape_text(&vcard.name)));
+        match &vcard.structured_name {
+            Some(name) => lines.push(format!("N:{}", structured_name_value(name))),
+            // required in vCard 3.0
+            None if self.version == Version::V3 => lines.push(String::from("N:;;;;")),
+            None => (),
+        }
+        if let Some(uid) = &vcard.uid {
+            lines.push(format!("UID:{}", escape_text(uid)));
+        }
+        if let Some(bday) = &vcard.bday {
+            lines.extend(self.date_property("BDAY", bday));
+        }
+
+        let mut item = 0;
+        for Event { kind, date } in &vcard.events {
+            let label = match kind {
+                EventKind::Anniversary => {
+                    let name = match self.version {
+                        Version::V3 => "X-ANNIVERSARY",
+                        Version::V4 => "ANNIVERSARY",
+                    };
+                    lines.extend(self.date_property(name, date));
+                    continue;
+                }
+                EventKind::Other => String::from("_$!<Other>!$_"),
+                EventKind::Custom(label) => escape_text(label),
+            };
+            if let Some(line) = self.date_property("X-ABDATE", date) {
+                item += 1;
+                lines.push(format!("item{}.{}", item, line));
+                lines.push(format!("item{}.X-ABLabel:{}", item, label));
+            }
+        }
+
+        lines.push(String::from("END:VCARD"));
+        lines
+    }
+
+    /// The content line of a date property, `None` if there's nothing to
+    /// write.
+    fn date_property(&self, name: &str, date: &DateAndOrTime) -> Option<String> {
+        let value = match (self.version, date) {
+            (Version::V3, DateAndOrTime::Text(_)) => return None,
+            (Version::V4, DateAndOrTime::Text(text)) => {
+                return Some(format!("{};VALUE=text:{}", name, escape_text(text)))
+            }
+            (Version::V3, DateAndOrTime::Date(date)) => date.format("%Y-%m-%d").to_string(),
+            (Version::V4, DateAndOrTime::Date(date)) => date.format("%Y%m%d").to_string(),
+            (Version::V3, DateAndOrTime::DateTime(date_time)) => {
+                date_time.format("%Y-%m-%dT%H:%M:%S").to_string()
+            }
+            (Version::V4, DateAndOrTime::DateTime(date_time)) => {
+                date_time.format("%Y%m%dT%H%M%S").to_string()
+            }
+            (
+                Version::V3,
+                DateAndOrTime::Partial {
+                    year: None,
+                    month: Some(month),
+                    day: Some(day),
+                },
+            ) => {
+                return Some(format!(
+                    "{};X-APPLE-OMIT-YEAR={year}:{year}-{:02}-{:02}",
+                    name,
+                    month,
+                    day,
+                    year = OMITTED_YEAR
+                ))
+            }
+            (
+                Version::V3,
+                DateAndOrTime::Partial {
+                    year: Some(year),
+                    month: Some(month),
+                    day: Some(day),
+                },
+            ) => format!("{:04}-{:02}-{:02}", year, month, day),
+            // vCard 3.0 has no syntax for the other partial dates
+            (Version::V3, DateAndOrTime::Partial { .. }) => return None,
+            (_, DateAndOrTime::Partial { year, month, day }) => match (year, month, day) {
+                (Some(year), Some(month), Some(day)) => {
+                    format!("{:04}{:02}{:02}", year, month, day)
+                }
+                (Some(year), Some(month), None) => format!("{:04}-{:02}", year, month),
+                (Some(year), None, _) => format!("{:04}", year),
+                (None, Some(month), Some(day)) => format!("--{:02}{:02}", month, day),
+                (None, Some(month), None) => format!("--{:02}", month),
+                (None, None, Some(day)) => format!("---{:02}", day),
+                (None, None, None) => return None,
+            },
+        };
+        Some(format!("{}:{}", name, value))
+    }
+}
+
+/// Writes all vCards into a string.
+pub fn write_vcards(vcards: &[VCard], version: Version) -> String {
+    let mut writer = VCardWriter::new(Vec::new(), version);
+    for vcard in vcards {
+        writer.write(vcard).expect("writing to a Vec doesn't fail");
+    }
+    String::from_utf8(writer.into_inner()).expect("only strings are written")
+}
+
+fn structured_name_value(name: &StructuredName) -> String {
+    [
+        &name.family,
+        &name.given,
+        &name.additional,
+        &name.prefixes,
+        &name.suffixes,
+    ]
+    .map(|values| {
+        values
+            .iter()
+            .map(|value| escape_text(value))
+            .collect::<Vec<_>>()
+            .join(",")
+    })
+    .join(";")
+}
+
+/// Folds a content line after [`MAX_LINE_LENGTH`] octets, without splitting
+/// multi-byte characters.
+pub(crate) fn fold(line: &str) -> String {
+    let mut folded = String::with_capacity(line.len() + line.len() / MAX_LINE_LENGTH * 3);
+    let mut length = 0;
+    for c in line.chars() {
+        if length + c.len_utf8() > MAX_LINE_LENGTH {
+            folded.push_str("\r\n ");
+            length = 1;
+        }
+        folded.push(c);
+        length += c.len_utf8();
+    }
+    folded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::parse_vcards;
+    use super::*;
+    use chrono::NaiveDate;
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    fn partial(year: Option<i32>, month: Option<u32>, day: Option<u32>) -> DateAndOrTime {
+        DateAndOrTime::Partial { year, month, day }
+    }
+
+    fn event(kind: EventKind, date: DateAndOrTime) -> Event {
+        Event { kind, date }
+    }
+
+    fn sample_vcards() -> Vec<VCard> {
+        vec![
+            VCard {
+                name: String::from("Allice Test"),
+                uid: Some(String::from(
+                    "urn:uuid:4fbe8971-0bc3-424c-9c26-36c3e1eff6b1",
+                )),
+                bday: Some(date(1980, 5, 7).into()),
+                ..Default::default()
+            },
+            VCard {
+                name: String::from("Smith, John; \\o/"),
+                structured_name: Some(StructuredName {
+                    family: vec![String::from("Smith; Jr.")],
+                    given: vec![String::from("John"), String::from("Jo")],
+                    prefixes: vec![String::from("Dr.")],
+                    ..Default::default()
+                }),
+                uid: Some(String::from("smith,john")),
+                bday: Some(partial(None, Some(2), Some(29))),
+                events: vec![
+                    event(
+                        EventKind::Custom(String::from("name day")),
+                        partial(None, Some(6), Some(24)),
+                    ),
+                    event(EventKind::Anniversary, date(2010, 8, 1).into()),
+                    event(
+                        EventKind::Other,
+                        DateAndOrTime::Text(String::from("some day, maybe")),
+                    ),
+                    event(EventKind::Anniversary, partial(None, Some(9), Some(1))),
+                ],
+            },
+            VCard {
+                name: "Ünïcödé ".repeat(20),
+                structured_name: None,
+                uid: None,
+                bday: Some(DateAndOrTime::DateTime(
+                    date(1980, 5, 7).and_hms_opt(12, 30, 0).unwrap(),
+                )),
+                events: vec![
+                    event(EventKind::Other, partial(Some(1999), Some(3), None)),
+                    event(EventKind::Other, partial(Some(1999), None, None)),
+                    event(EventKind::Other, partial(None, Some(3), None)),
+                    event(EventKind::Other, partial(None, None, Some(3))),
+                ],
+            },
+            VCard {
+                name: String::from("Line\nbreak"),
+                bday: Some(DateAndOrTime::Text(String::from("circa 1800"))),
+                ..Default::default()
+            },
+        ]
+    }
+
+    #[test]
+    fn write_v4() {
+        let vcards = [VCard {
+            name: String::from("Smith, John"),
+            structured_name: Some(StructuredName {
+                family: vec![String::from("Smith")],
+                given: vec![String::from("John")],
+                ..Default::default()
+            }),
+            uid: Some(String::from("1234")),
+            bday: Some(partial(None, Some(5), Some(7))),
+            events: vec![
+                event(EventKind::Anniversary, date(2010, 8, 1).into()),
+                event(
+                    EventKind::Custom(String::from("name day")),
+                    partial(None, Some(6), Some(24)),
+                ),
+            ],
+        }];
+        assert_eq!(
+            "\
+BEGIN:VCARD\r
+VERSION:4.0\r
+FN:Smith\\, John\r
+N:Smith;John;;;\r
+UID:1234\r
+BDAY:--0507\r
+ANNIVERSARY:20100801\r
+item1.X-ABDATE:--0624\r
+item1.X-ABLabel:name day\r
+END:VCARD\r
+",
+            write_vcards(&vcards, Version::V4)
+        );
+    }
+
+    #[test]
+    fn write_v3() {
+        let vcards = [VCard {
+            name: String::from("Bob Test"),
+            structured_name: None,
+            uid: None,
+            bday: Some(partial(None, Some(5), Some(7))),
+            events: vec![
+                event(EventKind::Anniversary, date(2010, 8, 1).into()),
+                event(EventKind::Other, date(2000, 1, 2).into()),
+            ],
+        }];
+        assert_eq!(
+            "\
+BEGIN:VCARD\r
+VERSION:3.0\r
+FN:Bob Test\r
+N:;;;;\r
+BDAY;X-APPLE-OMIT-YEAR=1604:1604-05-07\r
+X-ANNIVERSARY:2010-08-01\r
+item1.X-ABDATE:2000-01-02\r
+item1.X-ABLabel:_$!<Other>!$_\r
+END:VCARD\r
+",
+            write_vcards(&vcards, Version::V3)
+        );
+    }
+
+    #[test]
+    fn write_v3_leaves_out_dates_without_syntax() {
+        let vcards = [VCard {
+            name: String::from("Bob Test"),
+            bday: Some(DateAndOrTime::Text(String::from("circa 1800"))),
+            events: vec![
+                event(EventKind::Anniversary, partial(Some(1999), Some(3), None)),
+                event(EventKind::Other, partial(None, Some(3), None)),
+                event(EventKind::Other, partial(Some(2000), Some(1), Some(2))),
+            ],
+            ..Default::default()
+        }];
+        assert_eq!(
+            "\
+BEGIN:VCARD\r
+VERSION:3.0\r
+FN:Bob Test\r
+N:;;;;\r
+item1.X-ABDATE:2000-01-02\r
+item1.X-ABLabel:_$!<Other>!$_\r
+END:VCARD\r
+",
+            write_vcards(&vcards, Version::V3)
+        );
+    }
+
+    #[test]
+    fn fold_long_lines() {
+        let line = format!("NOTE:{}", "a".repeat(150));
+        let folded = fold(&line);
+        let lines: Vec<_> = folded.split("\r\n").collect();
+        assert_eq!(3, lines.len());
+        assert_eq!(75, lines[0].len());
+        assert_eq!(75, lines[1].len());
+        assert!(lines[1].starts_with(' '));
+
+        // never splits a multi-byte character
+        let folded = fold(&format!("FN:{}", "ä".repeat(50)));
+        for line in folded.split("\r\n") {
+            assert!(line.len() <= 75, "{}", line);
+        }
+        assert_eq!(
+            format!("FN:{}", "ä".repeat(50)),
+            folded.replace("\r\n ", "")
+        );
+    }
+
+    /// Whether vCard 3.0 has a syntax for the date.
+    fn is_v3_date(date: &DateAndOrTime) -> bool {
+        !matches!(date, DateAndOrTime::Text(_)) && date.month_day().is_some()
+    }
+
+    #[test]
+    fn round_trip() {
+        for version in [Version::V3, Version::V4] {
+            let mut vcards = sample_vcards();
+            if version == Version::V3 {
+                for vcard in &mut vcards {
+                    vcard.bday = vcard.bday.take().filter(is_v3_date);
+                    vcard.events.retain(|event| is_v3_date(&event.date));
+                }
+            }
+            let written = write_vcards(&sample_vcards(), version);
+            assert_eq!(Ok(&vcards), parse_vcards(&written).as_ref(), "{}", written);
+            // writing the parsed cards again gives the same file
+            assert_eq!(
+                written,
+                write_vcards(&parse_vcards(&written).unwrap(), version)
+            );
+        }
+    }
+}
@@ -0,0 +1,295 @@
+use super::encoding::escape_text;
+use super::{ErrorKind, Param, ParsedVCards, Property, VCard, VCardError};
+use crate::xml::{parse_xml, Element};
+
+/// Components of the structured properties, in the order of the vCard value.
+const STRUCTURED_PROPERTIES: [(&str, &[&str]); 3] = [
+    ("N", &["surname", "given", "additional", "prefix", "suffix"]),
+    (
+        "ADR",
+        &[
+            "pobox", "ext", "street", "locality", "region", "code", "country",
+        ],
+    ),
+    ("GENDER", &["sex", "identity"]),
+];
+
+/// Parses all xCards (RFC 6351) in `contents`, failing at the first error.
+/// `contents` is a `<vcards>` element with any number of `<vcard>` elements.
+pub fn parse_xcards(contents: impl AsRef<[u8]>) -> Result<Vec<VCard>, VCardError> {
+    read_xcards(contents.as_ref()).into_iter().collect()
+}
+
+/// Parses all xCards in `contents`, skipping broken cards instead of
+/// failing.
+pub fn parse_xcards_lenient(contents: impl AsRef<[u8]>) -> ParsedVCards {
+    read_xcards(contents.as_ref()).into_iter().collect()
+}
+
+fn read_xcards(contents: &[u8]) -> Vec<Result<VCard, VCardError>> {
+    let root = match std::str::from_utf8(contents)
+        .map_err(|e| e.to_string())
+        .and_then(parse_xml)
+    {
+        Ok(root) => root,
+        Err(e) => return vec![Err(invalid(e))],
+    };
+
+    match root.name.as_str() {
+        "vcards" => root.children_named("vcard").map(read_xcard).collect(),
+        "vcard" => vec![read_xcard(&root)],
+        _ => vec![Err(invalid(format!(
+            "expected <vcards>, got <{}>",
+            root.name
+        )))],
+    }
+}
+
+/// Converts the xCard to the properties of a vCard, so that it's read just
+/// like one.
+fn read_xcard(xcard: &Element) -> Result<VCard, VCardError> {
+    let mut properties = Vec::new();
+    for element in &xcard.children {
+        if element.name == "group" {
+            for property in &element.children {
+                properties.push(read_property(property, element.name_attribute.clone())?);
+            }
+        } else {
+            properties.push(read_property(element, None)?);
+        }
+    }
+
+    VCard::from_properties(&properties).map_err(|e| {
+        let card = properties
+            .iter()
+            .find(|property| ["FN", "UID"].contains(&property.name.as_str()))
+            .and_then(|property| property.text_value().ok());
+        e.in_card(card)
+    })
+}
+
+/// Reads a property like `<bday><date>19800507</date></bday>`.
+fn read_property(element: &Element, group: Option<String>) -> Result<Property, VCardError> {
+    let name = element.name.to_ascii_uppercase();
+
+    let mut params = Vec::new();
+    for param in element
+        .children_named("parameters")
+        .flat_map(|p| &p.children)
+    {
+        params.push(Param {
+            name: param.name.to_ascii_uppercase(),
+            values: param.children.iter().map(|v| v.text.clone()).collect(),
+        });
+    }
+
+    let values: Vec<&Element> = element
+        .children
+        .iter()
+        .filter(|child| child.name != "parameters")
+        .collect();
+    if values.is_empty() {
+        return Err(invalid(format!("no value in <{}>", element.name)));
+    }
+
+    let structured = STRUCTURED_PROPERTIES
+        .iter()
+        .find(|(structured, _)| *structured == name);
+    let value = match structured {
+        Some((_, components)) => components
+            .iter()
+            .map(|component| {
+                element
+                    .children_named(component)
+                    .map(|value| escape_text(&value.text))
+                    .collect::<Vec<_>>()
+                    .join(",")
+            })
+            .collect::<Vec<_>>()
+            .join(";"),
+        None => {
+            if values[0].name != "unknown" {
+                params.push(Param {
+                    name: String::from("VALUE"),
+                    values: vec![values[0].name.clone()],
+                });
+            }
+            // the units of ORG are its components, otherwise multiple values
+            // are a list (e.g. of NICKNAME)
+            let separator = if name == "ORG" { ";" } else { "," };
+            values
+                .iter()
+                .map(|value| escape_text(&value.text))
+                .collect::<Vec<_>>()
+                .join(separator)
+        }
+    };
+
+    Ok(Property {
+        group,
+        name,
+        params,
+        value,
+    })
+}
+
+fn invalid(msg: impl Into<String>) -> VCardError {
+    ErrorKind::InvalidXCardError(msg.into()).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vcard::{parse_vcards, DateAndOrTime, Event, EventKind, StructuredName};
+    use chrono::NaiveDate;
+
+    #[test]
+    fn parse_xcard() {
+        let input = r#"<?xml version="1.0" encoding="UTF-8"?>
+<vcards xmlns="urn:ietf:params:xml:ns:vcard-4.0">
+  <vcard>
+    <fn><text>Smith &amp; Sons, John</text></fn>
+    <n>
+      <surname>Smith</surname>
+      <given>John</given>
+      <additional>Alexander</additional>
+      <additional>Al</additional>
+      <prefix/>
+      <suffix/>
+    </n>
+    <uid><uri>urn:uuid:1234</uri></uid>
+    <bday><date-and-or-time>--0507</date-and-or-time></bday>
+    <anniversary><date>20100801</date></anniversary>
+    <group name="item1">
+      <x-abdate><date>2012-06-24</date></x-abdate>
+      <x-ablabel><unknown><![CDATA[name day]]></unknown></x-ablabel>
+    </group>
+    <tel>
+      <parameters><type><text>work</text><text>voice</text></type></parameters>
+      <uri>tel:+1-555-555-5555</uri>
+    </tel>
+  </vcard>
+</vcards>"#;
+
+        let result = parse_xcards(input).unwrap();
+
+        let date = |year, month, day| {
+            DateAndOrTime::Date(NaiveDate::from_ymd_opt(year, month, day).unwrap())
+        };
+        assert_eq!(
+            vec![VCard {
+                name: String::from("Smith & Sons, John"),
+                structured_name: Some(StructuredName {
+                    family: vec![String::from("Smith")],
+                    given: vec![String::from("John")],
+                    additional: vec![String::from("Alexander"), String::from("Al")],
+                    ..Default::default()
+                }),
+                uid: Some(String::from("urn:uuid:1234")),
+                bday: Some(DateAndOrTime::Partial {
+                    year: None,
+                    month: Some(5),
+                    day: Some(7)
+                }),
+                events: vec![
+                    Event {
+                        kind: EventKind::Anniversary,
+                        date: date(2010, 8, 1),
+                    },
+                    Event {
+                        kind: EventKind::Custom(String::from("name day")),
+                        date: date(2012, 6, 24),
+                    },
+                ],
+            }],
+            result
+        );
+    }
+
+    #[test]
+    fn parse_xcards_same_as_vcards() {
+        let xcards = r#"<vcards xmlns="urn:ietf:params:xml:ns:vcard-4.0">
+  <vcard>
+    <fn><text>Allice Test</text></fn>
+    <bday><date>1980-05-07</date></bday>
+  </vcard>
+  <vcard>
+    <n><surname>Test</surname><given>Bob</given><additional/><prefix/><suffix/></n>
+    <org><text>Test Inc.</text><text>Research</text></org>
+    <nickname><text>Bobby</text><text>B</text></nickname>
+    <bday><text>circa 1800</text></bday>
+  </vcard>
+  <vcard>
+    <org><text>Test; Inc.</text><text>Research</text></org>
+  </vcard>
+</vcards>"#;
+        let vcards = "\
+BEGIN:VCARD
+VERSION:4.0
+FN:Allice Test
+BDAY:1980-05-07
+END:VCARD
+BEGIN:VCARD
+VERSION:4.0
+N:Test;Bob;;;
+ORG:Test Inc.;Research
+NICKNAME:Bobby,B
+BDAY;VALUE=text:circa 1800
+END:VCARD
+BEGIN:VCARD
+VERSION:4.0
+ORG:Test\\; Inc.;Research
+END:VCARD
+";
+
+        assert_eq!(parse_vcards(vcards).unwrap(), parse_xcards(xcards).unwrap());
+    }
+
+    #[test]
+    fn parse_xcards_lenient_skips_broken_cards() {
+        let input = r#"<vcards xmlns="urn:ietf:params:xml:ns:vcard-4.0">
+  <vcard><fn><text>Allice Test</text></fn></vcard>
+  <vcard><fn><text>Bob Test</text></fn><bday><date>1980-asdf-07</date></bday></vcard>
+  <vcard><fn/></vcard>
+</vcards>"#;
+
+        let result = parse_xcards_lenient(input);
+
+        assert_eq!(
+            vec![VCard {
+                name: String::from("Allice Test"),
+                ..Default::default()
+            }],
+            result.vcards
+        );
+        assert_eq!(
+            vec![
+                (
+                    ErrorKind::InvalidBDayError(String::from("input contains invalid characters")),
+                    Some(String::from("Bob Test"))
+                ),
+                (
+                    ErrorKind::InvalidXCardError(String::from("no value in <fn>")),
+                    None
+                ),
+            ],
+            result
+                .diagnostics
+                .into_iter()
+                .map(|d| (d.kind, d.card))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn parse_xcards_invalid_xml() {
+        for input in ["BEGIN:VCARD", "<vcards><vcard></vcards>", "<contacts/>"] {
+            let result = parse_xcards(input).unwrap_err();
+            assert!(
+                matches!(result.kind, ErrorKind::InvalidXCardError(_)),
+                "{}",
+                input
+            );
+        }
+    }
+}
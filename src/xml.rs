@@ -0,0 +1,89 @@
+use quick_xml::escape::resolve_predefined_entity;
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::{Reader, XmlVersion};
+
+/// A parsed XML element, namespaces are ignored. Used for xCards and the
+/// WebDAV responses of CardDAV servers.
+#[derive(Debug, Default)]
+pub(crate) struct Element {
+    /// The local name, in lower case.
+    pub(crate) name: String,
+    /// The `name` attribute, used by `<group>`.
+    pub(crate) name_attribute: Option<String>,
+    pub(crate) children: Vec<Element>,
+    pub(crate) text: String,
+}
+
+impl Element {
+    pub(crate) fn children_named<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Element> {
+        self.children.iter().filter(move |child| child.name == name)
+    }
+}
+
+/// Parses the XML document into a tree of elements, returning the root.
+pub(crate) fn parse_xml(xml: &str) -> Result<Element, String> {
+    let mut reader = Reader::from_str(xml);
+    let mut stack: Vec<Element> = Vec::new();
+    loop {
+        let event = reader.read_event().map_err(|e| e.to_string())?;
+        let element = match event {
+            Event::Start(start) => {
+                stack.push(element(&start)?);
+                continue;
+            }
+            Event::Empty(start) => element(&start)?,
+            Event::End(_) => stack.pop().ok_or("unexpected end tag")?,
+            Event::Text(text) => {
+                if let Some(parent) = stack.last_mut() {
+                    parent.text.push_str(&text.xml10_content());
+                }
+                continue;
+            }
+            Event::CData(cdata) => {
+                if let Some(parent) = stack.last_mut() {
+                    parent.text.push_str(&cdata.xml10_content());
+                }
+                continue;
+            }
+            Event::GeneralRef(reference) => {
+                let resolved = match reference.resolve_char_ref().map_err(|e| e.to_string())? {
+                    Some(c) => String::from(c),
+                    None => resolve_predefined_entity(&reference)
+                        .map(String::from)
+                        .ok_or_else(|| format!("unknown entity &{};", &*reference))?,
+                };
+                if let Some(parent) = stack.last_mut() {
+                    parent.text.push_str(&resolved);
+                }
+                continue;
+            }
+            Event::Eof => return Err(String::from("no root element")),
+            _ => continue,
+        };
+
+        match stack.last_mut() {
+            Some(parent) => parent.children.push(element),
+            None => return Ok(element),
+        }
+    }
+}
+
+fn element(start: &BytesStart) -> Result<Element, String> {
+    let mut name_attribute = None;
+    for attribute in start.attributes() {
+        let attribute = attribute.map_err(|e| e.to_string())?;
+        if attribute.key.local_name().as_ref() == "name" {
+            name_attribute = Some(
+                attribute
+                    .normalized_value(XmlVersion::Implicit1_0)
+                    .map_err(|e| e.to_string())?
+                    .into_owned(),
+            );
+        }
+    }
+    Ok(Element {
+        name: start.local_name().as_ref().to_ascii_lowercase(),
+        name_attribute,
+        ..Default::default()
+    })
+}